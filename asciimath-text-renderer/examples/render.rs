@@ -1,7 +1,7 @@
 extern crate asciimath_text_renderer;
 
 fn main() {
-    let arg = std::env::args().last().unwrap().to_string();
+    let arg = std::env::args().next_back().unwrap().to_string();
     let rendered = asciimath_text_renderer::render(&arg);
     println!("{}", rendered);
 }
@@ -1,11 +1,25 @@
-use phf::phf_map;
+use std::ops::Range;
 
+use phf::{phf_map, phf_set};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::compact;
+use crate::font_style::{FontFallback, FontStyle};
+
+use crate::glyph_set::Charset;
+use crate::html::{self, canvas_to_html, HtmlOptions};
+use crate::line_break::{break_lines, RowItem};
+use crate::render_options::{RenderOptions, ScriptStyle};
+use crate::rendered_formula::RenderedFormula;
 use crate::renderer::{
-    BracketType, Div, Drawable, Expr, Group, Literal, Matrix, Root, ScriptExpr, Sqrt, Stack,
+    Accent, AccentType, BigOperator, BigOperatorType, BracketType, Colored, Div, Drawable, Expr,
+    Group, Literal, Matrix, Root, ScriptExpr, Spanned, Sqrt, Stack, UnderOver,
 };
+use crate::svg::{canvas_to_svg, SvgOptions};
+use crate::text_canvas::{Color, TextCanvas};
 
-
-static SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
+pub static SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
     //symbols taken from https://github.com/asciimath/asciimathml/blob/master/ASCIIMathML.js
 
     //greek letters
@@ -195,11 +209,72 @@ static SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
 
 };
 
-//ident substitutions - mostly to add some spacing
+//symbols written in ASCII charset, when asciimath spelling of
+//symbol (which is used otherwise) is not clear enough
+static ASCII_SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
+    "*" => "*",
+    "**" => "*",
+    "***" => "*",
+    "xx" => "x",
+    "-:" => "/",
+    "@" => "o",
+    "lt=" => "<=",
+    "gt=" => ">=",
+    "-lt" => "-<",
+    "not" => "!",
+    "(:" => "<",
+    ":)" => ">",
+    "<<" => "<",
+    ">>" => ">",
+    "uarr" => "^",
+    "darr" => "v",
+    "rarr" => "->",
+    "larr" => "<-",
+    "harr" => "<->",
+    "rArr" => "=>",
+    "lArr" => "<=",
+    "hArr" => "<=>",
+    "cdots" => "...",
+    //word operators, spaced so they don't run into their operands
+    "in" => " in ",
+    "!in" => " !in ",
+    "sub" => " sub ",
+    "sup" => " sup ",
+    "sube" => " sube ",
+    "supe" => " supe ",
+    "prop" => " prop ",
+    "mlt" => "<<",
+    "mgt" => ">>",
+    "ox" => " ox ",
+    "vv" => " vv ",
+    "nn" => " nn ",
+    "uu" => " uu ",
+    "AA" => " AA ",
+    "EE" => " EE ",
+};
+
+//ident substitutions, to add some spacing around operators
+//(applied only with operator_spacing option)
 static IDENTS: phf::Map<&'static str, &'static str> = phf_map! {
-    //"+" => " + ",
-    //"-" => " - ",
-    //"=" => " = ",
+    "+" => " + ",
+    "-" => " - ",
+};
+
+//symbols spaced like "+" and "-" with operator_spacing option
+static ADDITIVE_SYMBOLS: phf::Set<&'static str> = phf_set! {
+    "+-", "-+",
+};
+
+pub static RELATIONS: phf::Set<&'static str> = phf_set! {
+    "=", "!=", ":=", "<", "lt", "<=", "lt=", ">", "gt", "mlt", ">=", "gt=", "mgt",
+    "-<", "-lt", ">-", "-<=", ">-=", "in", "!in", "sub", "sup", "sube", "supe",
+    "-=", "~=", "~~", "~", "prop", "->", "rarr", "|->", "=>", "rArr", "<=>", "hArr", "iff",
+};
+
+//operators that take limits above and below them in display style
+pub static BIG_OPERATORS: phf::Set<&'static str> = phf_set! {
+    "sum", "prod", "int", "oint", "lim", "Lim", "min", "max",
+    "uuu", "bigcup", "nnn", "bigcap", "vvv", "bigvee", "^^^", "bigwedge",
 };
 
 pub fn bracket_type(bracket: &str) -> BracketType {
@@ -219,64 +294,559 @@ pub fn bracket_type(bracket: &str) -> BracketType {
     }
 }
 
+//error returned when expression cannot be rendered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderError {
+    //unary operator (like "cancel") that has no renderer yet
+    UnsupportedUnary { op: String, position: usize },
+    //binary operator (like "class") that has no renderer yet
+    UnsupportedBinary { op: String, position: usize },
+    //colour name in "color(...)" that is not recognized
+    UnknownColor { color: String, position: usize },
+    //letter with no styled form under font command, with FontFallback::Error
+    UnstyledCharacter { text: String, position: usize },
+}
+
+impl RenderError {
+    //name of the construct that failed, as written in the input
+    pub fn construct(&self) -> &str {
+        match self {
+            RenderError::UnsupportedUnary { op, .. }
+            | RenderError::UnsupportedBinary { op, .. } => op,
+            RenderError::UnknownColor { .. } => "color",
+            RenderError::UnstyledCharacter { text, .. } => text,
+        }
+    }
+
+    //byte offset of the construct in the input
+    pub fn position(&self) -> usize {
+        match self {
+            RenderError::UnsupportedUnary { position, .. }
+            | RenderError::UnsupportedBinary { position, .. }
+            | RenderError::UnknownColor { position, .. }
+            | RenderError::UnstyledCharacter { position, .. } => *position,
+        }
+    }
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::UnsupportedUnary { op, position } => {
+                write!(f, "unsupported unary operator \"{}\" at {}", op, position)
+            }
+            RenderError::UnsupportedBinary { op, position } => {
+                write!(f, "unsupported binary operator \"{}\" at {}", op, position)
+            }
+            RenderError::UnknownColor { color, position } => {
+                write!(f, "unknown color \"{}\" at {}", color, position)
+            }
+            RenderError::UnstyledCharacter { text, position } => {
+                write!(f, "no styled form for \"{}\" at {}", text, position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+//Operators visit_simple has a renderer for. Other output formats
+//reject the rest as well, so every format accepts the same expressions.
+pub fn check_unary(op: &str, source: &str) -> Result<(), RenderError> {
+    match op {
+        "sqrt" | "hat" | "bar" | "overline" | "vec" | "tilde" | "dot" | "ddot" | "ul"
+        | "underline" => Ok(()),
+        op if FontStyle::from_command(op).is_some() => Ok(()),
+        op => Err(RenderError::UnsupportedUnary {
+            op: op.to_string(),
+            position: position(source, op),
+        }),
+    }
+}
+
+pub fn check_binary(op: &str, source: &str) -> Result<(), RenderError> {
+    match op {
+        "frac" | "stackrel" | "root" | "color" => Ok(()),
+        op => Err(RenderError::UnsupportedBinary {
+            op: op.to_string(),
+            position: position(source, op),
+        }),
+    }
+}
+
+//parsed tree holds slices of the input, so position of a token
+//is just a distance between pointers
+pub fn position(source: &str, token: &str) -> usize {
+    let start = source.as_ptr() as usize;
+    let token_start = token.as_ptr() as usize;
+    if token_start >= start && token_start <= start + source.len() {
+        token_start - start
+    } else {
+        0
+    }
+}
+
+type VisitResult = Result<Option<Box<dyn Drawable>>, RenderError>;
+
+//byte range of a token within the input,
+//None for tokens that are not there (ie. missing closing bracket)
+fn token_span(source: &str, token: &str) -> Option<Range<usize>> {
+    let start = source.as_ptr() as usize;
+    let token_start = token.as_ptr() as usize;
+    if token.is_empty() || token_start < start || token_start + token.len() > start + source.len() {
+        None
+    } else {
+        Some(token_start - start..token_start - start + token.len())
+    }
+}
+
+//smallest range covering both
+fn join_spans(a: Option<Range<usize>>, b: Option<Range<usize>>) -> Option<Range<usize>> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.start.min(b.start)..a.end.max(b.end)),
+        (a, b) => a.or(b),
+    }
+}
+
+//part of the input that node was parsed from
+fn simple_span(simple: &asciimath_parser::tree::Simple, source: &str) -> Option<Range<usize>> {
+    match simple {
+        asciimath_parser::tree::Simple::Missing => None,
+        asciimath_parser::tree::Simple::Number(s)
+        | asciimath_parser::tree::Simple::Text(s)
+        | asciimath_parser::tree::Simple::Ident(s)
+        | asciimath_parser::tree::Simple::Symbol(s) => token_span(source, s),
+        asciimath_parser::tree::Simple::Unary(unary) => join_spans(
+            token_span(source, unary.op),
+            simple_span(unary.arg(), source),
+        ),
+        asciimath_parser::tree::Simple::Func(func) => join_spans(
+            token_span(source, func.func),
+            simple_span(func.arg(), source),
+        ),
+        asciimath_parser::tree::Simple::Binary(binary) => join_spans(
+            token_span(source, binary.op),
+            simple_span(binary.second(), source),
+        ),
+        asciimath_parser::tree::Simple::Group(group) => join_spans(
+            join_spans(
+                token_span(source, group.left_bracket),
+                token_span(source, group.right_bracket),
+            ),
+            expr_span(&group.expr, source),
+        ),
+        asciimath_parser::tree::Simple::Matrix(matrix) => join_spans(
+            token_span(source, matrix.left_bracket),
+            token_span(source, matrix.right_bracket),
+        ),
+    }
+}
+
+fn script_span(script: &asciimath_parser::tree::Script, source: &str) -> Option<Range<usize>> {
+    join_spans(
+        script.sub().and_then(|s| simple_span(s, source)),
+        script.sup().and_then(|s| simple_span(s, source)),
+    )
+}
+
+fn simple_script_span(
+    simple_script: &asciimath_parser::tree::SimpleScript,
+    source: &str,
+) -> Option<Range<usize>> {
+    join_spans(
+        simple_span(&simple_script.simple, source),
+        script_span(&simple_script.script, source),
+    )
+}
+
+fn script_func_span(
+    script_func: &asciimath_parser::tree::ScriptFunc,
+    source: &str,
+) -> Option<Range<usize>> {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => {
+            simple_script_span(simple_script, source)
+        }
+        asciimath_parser::tree::ScriptFunc::Func(func) => join_spans(
+            join_spans(
+                token_span(source, func.func),
+                script_span(&func.script, source),
+            ),
+            script_func_span(func.arg(), source),
+        ),
+    }
+}
+
+fn intermediate_span(
+    intermediate: &asciimath_parser::tree::Intermediate,
+    source: &str,
+) -> Option<Range<usize>> {
+    match intermediate {
+        asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+            script_func_span(script_func, source)
+        }
+        asciimath_parser::tree::Intermediate::Frac(fraction) => join_spans(
+            script_func_span(&fraction.numer, source),
+            script_func_span(&fraction.denom, source),
+        ),
+    }
+}
+
+fn expr_span(expr: &asciimath_parser::tree::Expression, source: &str) -> Option<Range<usize>> {
+    expr.iter().fold(None, |span, e| {
+        join_spans(span, intermediate_span(e, source))
+    })
+}
+
+//kind of node, as shown to users of RenderedFormula (ie. as HTML class)
+fn simple_kind(simple: &asciimath_parser::tree::Simple) -> &'static str {
+    match simple {
+        asciimath_parser::tree::Simple::Missing => "missing",
+        asciimath_parser::tree::Simple::Number(_) => "number",
+        asciimath_parser::tree::Simple::Text(_) => "text",
+        asciimath_parser::tree::Simple::Ident(_) => "identifier",
+        asciimath_parser::tree::Simple::Symbol(_) => "symbol",
+        asciimath_parser::tree::Simple::Unary(unary) => match unary.op {
+            "sqrt" => "sqrt",
+            op if FontStyle::from_command(op).is_some() => "font",
+            _ => "accent",
+        },
+        asciimath_parser::tree::Simple::Func(_) => "function",
+        asciimath_parser::tree::Simple::Binary(binary) => match binary.op {
+            "frac" => "fraction",
+            "root" => "root",
+            "stackrel" => "stackrel",
+            _ => "color",
+        },
+        asciimath_parser::tree::Simple::Group(_) => "group",
+        asciimath_parser::tree::Simple::Matrix(_) => "matrix",
+    }
+}
+
+//node remembering which part of the input it came from
+fn spanned(
+    span: Option<Range<usize>>,
+    kind: &'static str,
+    expr: Box<dyn Drawable>,
+) -> Box<dyn Drawable> {
+    match span {
+        Some(span) => Box::new(Spanned::new(span, kind, expr)),
+        None => expr,
+    }
+}
+
+//argument as it was written in the input, without brackets.
+//Used for arguments that are names rather than math, ie. "color(red)",
+//since parser splits them into single letter identifiers.
+pub fn raw_argument<'a>(simple: &asciimath_parser::tree::Simple<'a>, source: &'a str) -> &'a str {
+    match simple {
+        asciimath_parser::tree::Simple::Group(group) => {
+            let start = position(source, group.left_bracket) + group.left_bracket.len();
+            let end = if group.right_bracket.is_empty() {
+                source.len()
+            } else {
+                position(source, group.right_bracket)
+            };
+            source.get(start..end).unwrap_or("")
+        }
+        asciimath_parser::tree::Simple::Number(s)
+        | asciimath_parser::tree::Simple::Text(s)
+        | asciimath_parser::tree::Simple::Ident(s)
+        | asciimath_parser::tree::Simple::Symbol(s) => s,
+        _ => "",
+    }
+}
+
+//text under font command (ie. "bb x") is written with styled unicode characters,
+//token is the part of input text comes from
+fn styled_literal(
+    text: &str,
+    token: &str,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Box<dyn Drawable>, RenderError> {
+    //styled characters are not ASCII, so there is no way to show font
+    if let (Some(font), Charset::Unicode) = (font, options.glyphs.charset()) {
+        if options.font_fallback == FontFallback::Error && font.unstyled_char(text).is_some() {
+            return Err(RenderError::UnstyledCharacter {
+                text: token.to_string(),
+                position: position(source, token),
+            });
+        }
+        Ok(Box::new(Literal::with_ambiguous_width(
+            &font.apply(text),
+            options.ambiguous_width,
+        )))
+    } else {
+        Ok(Box::new(Literal::with_ambiguous_width(
+            text,
+            options.ambiguous_width,
+        )))
+    }
+}
+
+//in compact mode every node is rendered to a single line,
+//so nodes that would take more are replaced with text
+fn compact_literal(text: &str, options: &RenderOptions) -> Box<dyn Drawable> {
+    Box::new(Literal::with_ambiguous_width(text, options.ambiguous_width))
+}
+
+//text of already rendered single line node
+fn line(expr: &dyn Drawable, options: &RenderOptions) -> String {
+    expr.to_canvas(&options.glyphs).as_text()
+}
+
+//colour of every visible cell of single line node, if they share one
+fn line_color(expr: &dyn Drawable, options: &RenderOptions) -> Option<Color> {
+    let canvas = expr.to_canvas(&options.glyphs);
+    let mut colors = (0..expr.width())
+        .filter(|x| !canvas.get(*x, 0).trim().is_empty())
+        .map(|x| canvas.get_style(x, 0).color);
+    let first = colors.next()??;
+    colors.all(|c| c == Some(first)).then_some(first)
+}
+
+//text that replaces rendered nodes (ie. "½" for "1/2" or "x̄" for "bar x"),
+//keeps their colour if they all have the same one
+fn literal_replacing(
+    text: &str,
+    replaced: &[&dyn Drawable],
+    options: &RenderOptions,
+) -> Box<dyn Drawable> {
+    let colors: Vec<Option<Color>> = replaced.iter().map(|e| line_color(*e, options)).collect();
+    match colors.first() {
+        Some(Some(color)) if colors.iter().all(|c| *c == Some(*color)) => {
+            Box::new(Colored::new(*color, compact_literal(text, options)))
+        }
+        _ => compact_literal(text, options),
+    }
+}
+
+//argument in linear notation, in brackets unless it's a number or identifier
+fn compact_bracketed(arg: Box<dyn Drawable>, options: &RenderOptions) -> Vec<Box<dyn Drawable>> {
+    if compact::needs_brackets(&line(&*arg, options)) {
+        vec![
+            compact_literal("(", options),
+            arg,
+            compact_literal(")", options),
+        ]
+    } else {
+        vec![arg]
+    }
+}
+
+//name(arg1)(arg2), for constructs that have no compact form
+fn compact_call(
+    name: &str,
+    args: Vec<Box<dyn Drawable>>,
+    options: &RenderOptions,
+) -> Box<dyn Drawable> {
+    let mut row = vec![compact_literal(name, options)];
+    for arg in args {
+        row.push(compact_literal("(", options));
+        row.push(arg);
+        row.push(compact_literal(")", options));
+    }
+    Box::new(Expr::new(row))
+}
+
+fn fraction_node(
+    numer: Box<dyn Drawable>,
+    denom: Box<dyn Drawable>,
+    options: &RenderOptions,
+) -> Box<dyn Drawable> {
+    if options.compact {
+        if let Some(fraction) = compact::number_fraction(
+            &line(&*numer, options),
+            &line(&*denom, options),
+            options.glyphs.charset(),
+        ) {
+            return literal_replacing(&fraction, &[&*numer, &*denom], options);
+        }
+        //(a+b)/c
+        let mut row = compact_bracketed(numer, options);
+        row.push(compact_literal("/", options));
+        row.extend(compact_bracketed(denom, options));
+        Box::new(Expr::new(row))
+    } else {
+        Box::new(Div::new(numer, denom, options))
+    }
+}
+
 //bunch of visitors to map axiimath_parser hierarchy into tree of renderer structs
+//source is the parsed string, used to report error positions
+//and to record which part of it every node came from
 pub fn visit_simple(
     simple: &asciimath_parser::tree::Simple,
     omit_braces: bool,
-) -> Option<Box<dyn Drawable>> {
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    Ok(
+        visit_simple_node(simple, omit_braces, font, options, source)?
+            .map(|expr| spanned(simple_span(simple, source), simple_kind(simple), expr)),
+    )
+}
+
+fn visit_simple_node(
+    simple: &asciimath_parser::tree::Simple,
+    omit_braces: bool,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
     match simple {
-        asciimath_parser::tree::Simple::Missing => None,
-        asciimath_parser::tree::Simple::Number(number) => Some(Box::new(Literal::new(number))),
-        asciimath_parser::tree::Simple::Text(text) => Some(Box::new(Literal::new(text))),
-        asciimath_parser::tree::Simple::Ident(ident) => {
-            Some(Box::new(Literal::new(if let Some(s) = IDENTS.get(ident) {
-                s
-            } else {
-                ident
-            })))
+        asciimath_parser::tree::Simple::Missing => Ok(None),
+        asciimath_parser::tree::Simple::Number(number) => {
+            Ok(Some(styled_literal(number, number, font, options, source)?))
+        }
+        asciimath_parser::tree::Simple::Text(text) => {
+            Ok(Some(styled_literal(text, text, font, options, source)?))
         }
-        asciimath_parser::tree::Simple::Symbol(symbol) => Some(Box::new(Literal::new(
-            if let Some(s) = SYMBOLS.get(symbol) {
+        asciimath_parser::tree::Simple::Ident(ident) => Ok(Some(styled_literal(
+            match IDENTS.get(ident) {
+                Some(s) if options.operator_spacing => s,
+                _ => ident,
+            },
+            ident,
+            font,
+            options,
+            source,
+        )?)),
+        asciimath_parser::tree::Simple::Symbol(symbol) => {
+            let mut text = if *symbol == "*" {
+                options.multiplication_sign.glyph()
+            } else if let Some(s) = SYMBOLS.get(symbol) {
                 s
             } else {
                 symbol
-            },
-        ))),
+            };
+            if options.glyphs.charset() == Charset::Ascii && !text.is_ascii() {
+                text = ASCII_SYMBOLS.get(symbol).unwrap_or(symbol);
+            }
+            if options.operator_spacing
+                && (RELATIONS.contains(symbol) || ADDITIVE_SYMBOLS.contains(symbol))
+            {
+                Ok(Some(styled_literal(
+                    &format!(" {} ", text.trim()),
+                    symbol,
+                    font,
+                    options,
+                    source,
+                )?))
+            } else {
+                Ok(Some(styled_literal(text, symbol, font, options, source)?))
+            }
+        }
         asciimath_parser::tree::Simple::Unary(unary) => match unary.op {
-            "sqrt" => Some(Box::new(Sqrt::new(
-                visit_simple(unary.arg(), true).unwrap(),
-            ))),
-            "bar" => unimplemented!(),
-            "hat" => unimplemented!(),
-            "ul" => unimplemented!(),
-            "vec" => unimplemented!(),
-            "tilde" => unimplemented!(),
-            "dot" => unimplemented!(),
-            "ddot" => unimplemented!(),
-            _ => unimplemented!(), //TODO: implementation and test for all unary functions
+            "sqrt" => {
+                let arg = visit_arg(unary.arg(), font, options, source)?;
+                if options.compact {
+                    Ok(Some(match compact::sqrt_sign(options.glyphs.charset()) {
+                        //√x or √(x+1)
+                        Some(sign) => {
+                            let mut row = vec![compact_literal(sign, options)];
+                            row.extend(compact_bracketed(arg, options));
+                            Box::new(Expr::new(row))
+                        }
+                        None => compact_call("sqrt", vec![arg], options),
+                    }))
+                } else {
+                    Ok(Some(Box::new(Sqrt::new(arg))))
+                }
+            }
+            "hat" => visit_accent(AccentType::Hat, unary.arg(), font, options, source),
+            "bar" | "overline" => visit_accent(AccentType::Bar, unary.arg(), font, options, source),
+            "vec" => visit_accent(AccentType::Vector, unary.arg(), font, options, source),
+            "tilde" => visit_accent(AccentType::Tilde, unary.arg(), font, options, source),
+            "dot" => visit_accent(AccentType::Dot, unary.arg(), font, options, source),
+            "ddot" => visit_accent(AccentType::DoubleDot, unary.arg(), font, options, source),
+            "ul" | "underline" => {
+                visit_accent(AccentType::Underline, unary.arg(), font, options, source)
+            }
+            op if FontStyle::from_command(op).is_some() => Ok(Some(visit_arg(
+                unary.arg(),
+                FontStyle::from_command(op),
+                options,
+                source,
+            )?)),
+            //TODO: implementation and test for all unary functions
+            op => Err(RenderError::UnsupportedUnary {
+                op: op.to_string(),
+                position: position(source, op),
+            }),
         },
-        asciimath_parser::tree::Simple::Func(_func) => None, //TODO: handle func
+        asciimath_parser::tree::Simple::Func(func) => {
+            //function in simple context (ie. "sqrt sin x") can't have scripts
+            let arg = visit_simple(func.arg(), false, font, options, source)?;
+            Ok(Some(func_with_arg(
+                func.func,
+                styled_literal(func.func, func.func, font, options, source)?,
+                arg,
+                is_bracketed(func.arg()),
+            )))
+        }
         asciimath_parser::tree::Simple::Binary(binary) => {
             match binary.op {
-                "frac" => Some(Box::new(Div::new(
-                    visit_simple(binary.first(), true).unwrap(),
-                    visit_simple(binary.second(), true).unwrap(),
-                ))),
-                "stackrel" => Some(Box::new(Stack::new(
-                    visit_simple(binary.first(), true).unwrap(),
-                    visit_simple(binary.second(), true).unwrap(),
-                ))),
-                "root" => Some(Box::new(Root::new(
-                    visit_simple(binary.first(), true).unwrap(),
-                    visit_simple(binary.second(), true).unwrap(),
+                "frac" => Ok(Some(fraction_node(
+                    visit_arg(binary.first(), font, options, source)?,
+                    visit_arg(binary.second(), font, options, source)?,
+                    options,
                 ))),
+                "stackrel" => {
+                    let top = visit_arg(binary.first(), font, options, source)?;
+                    let base = visit_arg(binary.second(), font, options, source)?;
+                    if options.compact {
+                        Ok(Some(compact_call("stackrel", vec![top, base], options)))
+                    } else {
+                        Ok(Some(Box::new(Stack::new(top, base))))
+                    }
+                }
+                "root" => {
+                    let index = visit_arg(binary.first(), font, options, source)?;
+                    let radicand = visit_arg(binary.second(), font, options, source)?;
+                    if options.compact {
+                        //∛x, ⁵√x or root(n+1)(x)
+                        let radical =
+                            compact::radical(&line(&*index, options), options.glyphs.charset());
+                        Ok(Some(match radical {
+                            Some(radical) => {
+                                let mut row =
+                                    vec![literal_replacing(&radical, &[&*index], options)];
+                                row.extend(compact_bracketed(radicand, options));
+                                Box::new(Expr::new(row))
+                            }
+                            None => compact_call("root", vec![index, radicand], options),
+                        }))
+                    } else {
+                        Ok(Some(Box::new(Root::new(index, radicand))))
+                    }
+                }
+                "color" => {
+                    let color_name = raw_argument(binary.first(), source);
+                    let color =
+                        Color::from_name(color_name).ok_or_else(|| RenderError::UnknownColor {
+                            color: color_name.to_string(),
+                            position: position(source, color_name),
+                        })?;
+                    Ok(Some(Box::new(Colored::new(
+                        color,
+                        visit_arg(binary.second(), font, options, source)?,
+                    ))))
+                }
 
-                _ => unimplemented!(), //TODO: implementation and test for all binary functions
+                //TODO: implementation and test for all binary functions
+                op => Err(RenderError::UnsupportedBinary {
+                    op: op.to_string(),
+                    position: position(source, op),
+                }),
             }
         }
         asciimath_parser::tree::Simple::Group(group) => {
-            let rendered_expr = visit_expr(&group.expr); //can be empoty, ie. "f()"
-            Some(Box::new(Group::new(
+            let rendered_expr = visit_expr(&group.expr, font, options, source)?; //can be empoty, ie. "f()"
+            Ok(Some(Box::new(Group::new(
                 if omit_braces {
                     BracketType::None
                 } else {
@@ -288,130 +858,1008 @@ pub fn visit_simple(
                 } else {
                     bracket_type(group.right_bracket)
                 },
-            )))
+                options,
+            ))))
         }
         asciimath_parser::tree::Simple::Matrix(matrix) => {
             let mut exprs: Vec<Box<dyn Drawable>> = vec![];
-            matrix.rows().for_each(|row| {
+            for row in matrix.rows() {
                 for e in row {
-                    exprs.push(visit_expr(e).unwrap());
+                    exprs.push(visit_arg_expr(e, font, options, source)?);
                 }
-            });
-            Some(Box::new(Matrix::new(
+            }
+            if options.compact {
+                //[[a,b],[c,d]]
+                let num_cols = matrix.num_cols();
+                let mut row = vec![compact_literal(matrix.left_bracket, options)];
+                for (i, cell) in exprs.into_iter().enumerate() {
+                    let separator = match (i % num_cols, i) {
+                        (0, 0) => matrix.left_bracket.to_string(),
+                        (0, _) => format!("{},{}", matrix.right_bracket, matrix.left_bracket),
+                        _ => ",".to_string(),
+                    };
+                    row.push(compact_literal(&separator, options));
+                    row.push(cell);
+                }
+                row.push(compact_literal(
+                    &format!("{}{}", matrix.right_bracket, matrix.right_bracket),
+                    options,
+                ));
+                return Ok(Some(Box::new(Expr::new(row))));
+            }
+            Ok(Some(Box::new(Matrix::new(
                 bracket_type(matrix.left_bracket),
                 exprs,
                 bracket_type(matrix.right_bracket),
                 matrix.num_cols(),
-            )))
-        } //TODO: handle matrix
+                options,
+            ))))
+        }
     }
 }
 
-pub fn visit_simple_script(
-    simple_script: &asciimath_parser::tree::SimpleScript,
-    omit_braces: bool,
-) -> Option<Box<dyn Drawable>> {
-    if let Some(expr) = visit_simple(&simple_script.simple, omit_braces) {
-        match &simple_script.script {
-            asciimath_parser::tree::Script::None => Some(expr),
-            asciimath_parser::tree::Script::Sub(simple) => {
-                let sub_expr = visit_simple(simple, true).unwrap();
-                Some(Box::new(ScriptExpr::new(expr, Some(sub_expr), None)))
-            }
-            asciimath_parser::tree::Script::Super(simple) => {
-                let sup_expr = visit_simple(simple, true).unwrap();
-                Some(Box::new(ScriptExpr::new(expr, None, Some(sup_expr))))
-            }
-            asciimath_parser::tree::Script::Subsuper(simple1, simple2) => {
-                let sub_expr = visit_simple(simple1, true).unwrap();
-                let sup_expr = visit_simple(simple2, true).unwrap();
-                Some(Box::new(ScriptExpr::new(
-                    expr,
-                    Some(sub_expr),
-                    Some(sup_expr),
-                )))
-            }
+//single grapheme gets combining character (x̄) to stay one row tall,
+//anything bigger gets accent drawn in a separate row
+fn visit_accent(
+    accent_type: AccentType,
+    simple: &asciimath_parser::tree::Simple,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    let expr = visit_arg(simple, font, options, source)?;
+    let unicode = options.glyphs.charset() == Charset::Unicode;
+    if expr.width() == 1 && expr.height() == 1 && unicode {
+        let mut text = expr.as_text();
+        text.push(accent_type.combining_char());
+        Ok(Some(literal_replacing(&text, &[&*expr], options)))
+    } else if options.compact {
+        let text = line(&*expr, options);
+        if unicode && matches!(accent_type, AccentType::Bar | AccentType::Underline) {
+            //lines over every grapheme join into one
+            let text: String = text
+                .graphemes(true)
+                .map(|g| format!("{}{}", g, accent_type.combining_char()))
+                .collect();
+            Ok(Some(literal_replacing(&text, &[&*expr], options)))
+        } else {
+            Ok(Some(compact_call(
+                accent_type.command(),
+                vec![expr],
+                options,
+            )))
         }
     } else {
-        None
+        Ok(Some(Box::new(Accent::new(accent_type, expr))))
     }
 }
 
-pub fn visit_func(func: &asciimath_parser::tree::Func) -> Option<Box<dyn Drawable>> {
-    let arg = visit_script_func(func.arg(), false);
-    let func_lit = Box::new(Literal::new(func.func));
+//operator argument, which may be missing (ie. "sqrt" at the end of input),
+//in which case it's rendered as empty space
+fn visit_arg(
+    simple: &asciimath_parser::tree::Simple,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Box<dyn Drawable>, RenderError> {
+    Ok(visit_simple(simple, true, font, options, source)?
+        .unwrap_or_else(|| Box::new(Literal::new(""))))
+}
 
-    let func_expr: Option<Box<dyn Drawable>> = match &func.script {
-        asciimath_parser::tree::Script::None => Some(func_lit),
+fn visit_arg_expr(
+    expr: &asciimath_parser::tree::Expression,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Box<dyn Drawable>, RenderError> {
+    Ok(visit_expr(expr, font, options, source)?.unwrap_or_else(|| Box::new(Literal::new(""))))
+}
+
+//limits: whether scripts belong to big operator
+pub fn visit_script(
+    expr: Box<dyn Drawable>,
+    script: &asciimath_parser::tree::Script,
+    limits: bool,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Box<dyn Drawable>, RenderError> {
+    let (sub_expr, sup_expr) = match script {
+        asciimath_parser::tree::Script::None => (None, None),
         asciimath_parser::tree::Script::Sub(simple) => {
-            let sub_expr = visit_simple(simple, true).unwrap();
-            Some(Box::new(ScriptExpr::new(func_lit, Some(sub_expr), None)))
+            (visit_simple(simple, true, font, options, source)?, None)
         }
         asciimath_parser::tree::Script::Super(simple) => {
-            let sup_expr = visit_simple(simple, true).unwrap();
-            Some(Box::new(ScriptExpr::new(func_lit, None, Some(sup_expr))))
+            (None, visit_simple(simple, true, font, options, source)?)
         }
-        asciimath_parser::tree::Script::Subsuper(simple1, simple2) => {
-            let sub_expr = visit_simple(simple1, false).unwrap();
-            let sup_expr = visit_simple(simple2, false).unwrap();
-            Some(Box::new(ScriptExpr::new(
-                func_lit,
-                Some(sub_expr),
-                Some(sup_expr),
-            )))
+        asciimath_parser::tree::Script::Subsuper(simple1, simple2) => (
+            visit_simple(simple1, true, font, options, source)?,
+            visit_simple(simple2, true, font, options, source)?,
+        ),
+    };
+    if sub_expr.is_none() && sup_expr.is_none() {
+        Ok(expr)
+    } else if options.compact {
+        //x², aₙ or x^(a/b), base node is kept as it is
+        let mut row = vec![expr];
+        let scripts = compact::scripts(
+            sub_expr.as_ref().map(|e| line(&**e, options)).as_deref(),
+            sup_expr.as_ref().map(|e| line(&**e, options)).as_deref(),
+            options.glyphs.charset(),
+        );
+        for (script, marker, converted) in [
+            (sub_expr, "_", scripts.as_ref().and_then(|s| s.0.as_deref())),
+            (sup_expr, "^", scripts.as_ref().and_then(|s| s.1.as_deref())),
+        ] {
+            match (script, converted) {
+                (Some(script), Some(converted)) => {
+                    row.push(literal_replacing(converted, &[&*script], options))
+                }
+                (Some(script), None) => {
+                    row.push(compact_literal(marker, options));
+                    row.extend(compact_bracketed(script, options));
+                }
+                (None, _) => {}
+            }
         }
+        Ok(Box::new(Expr::new(row)))
+    } else if limits && options.script_style == ScriptStyle::Display {
+        Ok(Box::new(UnderOver::new(expr, sub_expr, sup_expr)))
+    } else {
+        Ok(Box::new(ScriptExpr::new(expr, sub_expr, sup_expr)))
+    }
+}
+
+pub fn visit_simple_script(
+    simple_script: &asciimath_parser::tree::SimpleScript,
+    omit_braces: bool,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    if let Some(expr) = visit_simple(&simple_script.simple, omit_braces, font, options, source)? {
+        let limits = matches!(
+            simple_script.simple,
+            asciimath_parser::tree::Simple::Symbol(symbol) if BIG_OPERATORS.contains(symbol)
+        );
+        let script_expr = visit_script(expr, &simple_script.script, limits, font, options, source)?;
+        Ok(Some(match simple_script.script {
+            asciimath_parser::tree::Script::None => script_expr,
+            _ => spanned(
+                simple_script_span(simple_script, source),
+                "script",
+                script_expr,
+            ),
+        }))
+    } else {
+        Ok(None)
+    }
+}
+
+//whether argument will be rendered within visible brackets, ie. "(x)" but not "{:x:}"
+fn is_bracketed(simple: &asciimath_parser::tree::Simple) -> bool {
+    match simple {
+        asciimath_parser::tree::Simple::Group(group) => {
+            bracket_type(group.left_bracket) != BracketType::None
+        }
+        asciimath_parser::tree::Simple::Matrix(_) => true,
+        _ => false,
+    }
+}
+
+//join function name (with its scripts) and argument.
+//Function names are multi-letter words (unlike f and g),
+//so non-bracketed argument is separated with a space: "sin x", but "sin(x)"
+fn func_with_arg(
+    name: &str,
+    func_expr: Box<dyn Drawable>,
+    arg: Option<Box<dyn Drawable>>,
+    bracketed_arg: bool,
+) -> Box<dyn Drawable> {
+    match arg {
+        Some(arg) if !bracketed_arg && name.chars().count() > 1 => {
+            Box::new(Expr::new(vec![func_expr, Box::new(Literal::new(" ")), arg]))
+        }
+        Some(arg) => Box::new(Expr::new(vec![func_expr, arg])),
+        None => func_expr,
+    }
+}
+
+pub fn visit_func(
+    func: &asciimath_parser::tree::Func,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    let arg = visit_script_func(func.arg(), false, font, options, source)?;
+    let func_lit = styled_literal(func.func, func.func, font, options, source)?;
+    //scripts belong to function name, ie. "sin^2 x"
+    let func_expr = visit_script(
+        func_lit,
+        &func.script,
+        BIG_OPERATORS.contains(func.func),
+        font,
+        options,
+        source,
+    )?;
+    let bracketed_arg = match func.arg() {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => {
+            is_bracketed(&simple_script.simple)
+        }
+        asciimath_parser::tree::ScriptFunc::Func(_) => false,
     };
-    Some(Box::new(Expr::new(vec![func_expr.unwrap(), arg.unwrap()])))
+    let span = join_spans(
+        join_spans(
+            token_span(source, func.func),
+            script_span(&func.script, source),
+        ),
+        script_func_span(func.arg(), source),
+    );
+    Ok(Some(spanned(
+        span,
+        "function",
+        func_with_arg(func.func, func_expr, arg, bracketed_arg),
+    )))
 }
 
 pub fn visit_script_func(
     script_func: &asciimath_parser::tree::ScriptFunc,
     omit_braces: bool,
-) -> Option<Box<dyn Drawable>> {
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
     match script_func {
         asciimath_parser::tree::ScriptFunc::Simple(simple_script) => {
-            visit_simple_script(simple_script, omit_braces)
+            visit_simple_script(simple_script, omit_braces, font, options, source)
         }
-        asciimath_parser::tree::ScriptFunc::Func(func) => visit_func(func),
+        asciimath_parser::tree::ScriptFunc::Func(func) => visit_func(func, font, options, source),
     }
 }
 
-pub fn visit_fraction(fraction: &asciimath_parser::tree::Frac) -> Option<Box<dyn Drawable>> {
-    Some(Box::new(Div::new(
-        visit_script_func(&fraction.numer, true).unwrap(),
-        visit_script_func(&fraction.denom, true).unwrap(),
+pub fn visit_fraction(
+    fraction: &asciimath_parser::tree::Frac,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    let numer = visit_script_func(&fraction.numer, true, font, options, source)?;
+    let denom = visit_script_func(&fraction.denom, true, font, options, source)?;
+    Ok(Some(spanned(
+        join_spans(
+            script_func_span(&fraction.numer, source),
+            script_func_span(&fraction.denom, source),
+        ),
+        "fraction",
+        fraction_node(
+            numer.unwrap_or_else(|| Box::new(Literal::new(""))),
+            denom.unwrap_or_else(|| Box::new(Literal::new(""))),
+            options,
+        ),
     )))
 }
 
-pub fn visit_expr(expr: &asciimath_parser::tree::Expression) -> Option<Box<dyn Drawable>> {
-    let mut r_expr = Expr::new(vec![]);
-    for e in expr.iter() {
-        match e {
-            asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
-                if let Some(_e) = visit_script_func(script_func, false) {
-                    r_expr.exprs.push(_e)
-                }
-            }
-            asciimath_parser::tree::Intermediate::Frac(fraction) => {
-                if let Some(_e) = visit_fraction(fraction) {
-                    r_expr.exprs.push(_e)
-                }
+pub fn visit_intermediate(
+    intermediate: &asciimath_parser::tree::Intermediate,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    match intermediate {
+        asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+            visit_script_func(script_func, false, font, options, source)
+        }
+        asciimath_parser::tree::Intermediate::Frac(fraction) => {
+            visit_fraction(fraction, font, options, source)
+        }
+    }
+}
+
+//big operator that is drawn with multi-row glyph when next to tall operand
+fn stretchy_operator<'a, 'b>(
+    intermediate: &'b asciimath_parser::tree::Intermediate<'a>,
+) -> Option<(
+    BigOperatorType,
+    &'b asciimath_parser::tree::SimpleScript<'a>,
+)> {
+    if let asciimath_parser::tree::Intermediate::ScriptFunc(
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script),
+    ) = intermediate
+    {
+        let operator_type = match simple_script.simple {
+            asciimath_parser::tree::Simple::Symbol("sum") => BigOperatorType::Sum,
+            asciimath_parser::tree::Simple::Symbol("prod") => BigOperatorType::Product,
+            asciimath_parser::tree::Simple::Symbol("int") => BigOperatorType::Integral,
+            _ => return None,
+        };
+        Some((operator_type, simple_script))
+    } else {
+        None
+    }
+}
+
+//relation or additive operator on its own, where formula can be broken
+fn is_break_point(intermediate: &asciimath_parser::tree::Intermediate) -> bool {
+    if let asciimath_parser::tree::Intermediate::ScriptFunc(
+        asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+            simple,
+            script: asciimath_parser::tree::Script::None,
+        }),
+    ) = intermediate
+    {
+        match simple {
+            asciimath_parser::tree::Simple::Ident(ident) => IDENTS.contains_key(ident),
+            asciimath_parser::tree::Simple::Symbol(symbol) => {
+                RELATIONS.contains(symbol) || ADDITIVE_SYMBOLS.contains(symbol)
             }
+            _ => false,
         }
+    } else {
+        false
     }
-    Some(Box::new(r_expr))
 }
 
-pub fn render(expr: &str) -> String {
+pub fn visit_expr(
+    expr: &asciimath_parser::tree::Expression,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> VisitResult {
+    let items = visit_expr_items(expr, font, options, source)?;
+    Ok(Some(Box::new(Expr::new(
+        items.into_iter().map(|(item, _)| item).collect(),
+    ))))
+}
+
+//nodes of expression row, each with a flag telling if it's a break point
+fn visit_expr_items(
+    expr: &asciimath_parser::tree::Expression,
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Vec<RowItem>, RenderError> {
+    visit_row_items(expr, font, options, source)
+}
+
+fn visit_row_items(
+    intermediates: &[asciimath_parser::tree::Intermediate],
+    font: Option<FontStyle>,
+    options: &RenderOptions,
+    source: &str,
+) -> Result<Vec<RowItem>, RenderError> {
+    let mut exprs: Vec<RowItem> = vec![];
+    let mut i = 0;
+    while i < intermediates.len() {
+        let e = &intermediates[i];
+        i += 1;
+        if let Some((operator_type, simple_script)) = stretchy_operator(e) {
+            //operator glyph is sized to its operand, so operand is rendered first.
+            //Operand runs up to next relation or additive operator, ie. "int x/2 dx = 1"
+            let end = intermediates[i..]
+                .iter()
+                .position(is_break_point)
+                .map_or(intermediates.len(), |p| i + p);
+            let operand = visit_row_items(&intermediates[i..end], font, options, source)?;
+            i = end;
+            let (above, below) = operand
+                .iter()
+                .map(|(o, _)| (o.level(), o.height() - o.level() - 1))
+                .fold((0, 0), |(a, b), (level, under)| {
+                    (a.max(level), b.max(under))
+                });
+            let (height, level) = (above + below + 1, above);
+            let operator = spanned(
+                simple_span(&simple_script.simple, source),
+                "symbol",
+                Box::new(BigOperator::new(operator_type, height, level)),
+            );
+            let operator =
+                visit_script(operator, &simple_script.script, true, font, options, source)?;
+            exprs.push((
+                match simple_script.script {
+                    asciimath_parser::tree::Script::None => operator,
+                    _ => spanned(
+                        simple_script_span(simple_script, source),
+                        "script",
+                        operator,
+                    ),
+                },
+                false,
+            ));
+            exprs.extend(operand);
+        } else if let Some(_e) = visit_intermediate(e, font, options, source)? {
+            exprs.push((_e, is_break_point(e)))
+        }
+    }
+    Ok(exprs)
+}
+
+//canvas and its level, the row that lines up with surrounding text
+fn render_canvas(expr: &str, options: &RenderOptions) -> Result<(TextCanvas, usize), RenderError> {
     //oddly, it doesn't return result, always parsing as something
     let parsed = asciimath_parser::parse(expr);
     //println!("{:#?}", parsed);
-    let expr_opt = visit_expr(&parsed);
+    let items = visit_expr_items(&parsed, None, options, expr)?;
+    let unbroken = |items: Vec<RowItem>| {
+        let expr = Expr::new(items.into_iter().map(|(item, _)| item).collect());
+        (expr.to_canvas(&options.glyphs), expr.level())
+    };
+    let Some(max_width) = options.max_width else {
+        return Ok(unbroken(items));
+    };
+    if items.iter().map(|(item, _)| item.width()).sum::<usize>() <= max_width {
+        return Ok(unbroken(items));
+    }
+    if let Some(broken) = break_lines(&items, max_width, &options.glyphs) {
+        return Ok(broken);
+    }
+    //some part is too wide, even alone in a line
+    let compact_options = RenderOptions {
+        compact: true,
+        ..options.clone()
+    };
+    let items = visit_expr_items(&parsed, None, &compact_options, expr)?;
+    match break_lines(&items, max_width, &options.glyphs) {
+        Some(broken) => Ok(broken),
+        None => Ok(unbroken(items)),
+    }
+}
 
-    //println!("{:#?}", expr_opt);
-    if let Some(expr) = expr_opt {
-        expr.as_text()
-    } else {
-        "".to_string()
+//render expression, reporting constructs that cannot be rendered
+pub fn try_render(expr: &str) -> Result<String, RenderError> {
+    try_render_with(expr, &RenderOptions::default())
+}
+
+pub fn try_render_with(expr: &str, options: &RenderOptions) -> Result<String, RenderError> {
+    Ok(render_canvas(expr, options)?.0.as_text())
+}
+
+//render expression with colours as ANSI escape sequences, for terminal output
+pub fn try_render_ansi(expr: &str) -> Result<String, RenderError> {
+    try_render_ansi_with(expr, &RenderOptions::default())
+}
+
+pub fn try_render_ansi_with(expr: &str, options: &RenderOptions) -> Result<String, RenderError> {
+    Ok(render_canvas(expr, options)?.0.as_ansi_text())
+}
+
+//render expression, keeping track of which part of it every cell came from
+pub fn try_render_formula(expr: &str) -> Result<RenderedFormula, RenderError> {
+    try_render_formula_with(expr, &RenderOptions::default())
+}
+
+pub fn try_render_formula_with(
+    expr: &str,
+    options: &RenderOptions,
+) -> Result<RenderedFormula, RenderError> {
+    let (canvas, baseline) = render_canvas(expr, options)?;
+    Ok(RenderedFormula::new(canvas, baseline))
+}
+
+//render expression as HTML fragment, see html module
+pub fn try_render_html(expr: &str) -> Result<String, RenderError> {
+    try_render_html_with(expr, &RenderOptions::default(), &HtmlOptions::default())
+}
+
+pub fn try_render_html_with(
+    expr: &str,
+    options: &RenderOptions,
+    html_options: &HtmlOptions,
+) -> Result<String, RenderError> {
+    Ok(canvas_to_html(
+        &render_canvas(expr, options)?.0,
+        expr,
+        html_options,
+    ))
+}
+
+//lossy version of try_render_html
+pub fn render_html(expr: &str) -> String {
+    try_render_html(expr)
+        .unwrap_or_else(|_| format!("<pre class=\"am-formula\">{}</pre>", html::escape(expr)))
+}
+
+//render expression as SVG image, see svg module
+pub fn try_render_svg(expr: &str) -> Result<String, RenderError> {
+    try_render_svg_with(expr, &RenderOptions::default(), &SvgOptions::default())
+}
+
+pub fn try_render_svg_with(
+    expr: &str,
+    options: &RenderOptions,
+    svg_options: &SvgOptions,
+) -> Result<String, RenderError> {
+    Ok(canvas_to_svg(&render_canvas(expr, options)?.0, svg_options))
+}
+
+//lossy version of try_render_svg, showing expression as it is
+pub fn render_svg(expr: &str) -> String {
+    try_render_svg(expr).unwrap_or_else(|_| {
+        canvas_to_svg(
+            &Literal::new(expr).to_canvas(&Default::default()),
+            &SvgOptions::default(),
+        )
+    })
+}
+
+//lossy version of try_render: if expression cannot be rendered,
+//it's returned as is
+pub fn render(expr: &str) -> String {
+    try_render(expr).unwrap_or_else(|_| expr.to_string())
+}
+
+//lossy version of try_render_with
+pub fn render_with(expr: &str, options: &RenderOptions) -> String {
+    try_render_with(expr, options).unwrap_or_else(|_| expr.to_string())
+}
+
+//break lines wider than max_width (in terminal columns)
+pub fn try_render_with_max_width(expr: &str, max_width: usize) -> Result<String, RenderError> {
+    try_render_with(
+        expr,
+        &RenderOptions {
+            max_width: Some(max_width),
+            ..Default::default()
+        },
+    )
+}
+
+//lossy version of try_render_with_max_width
+pub fn render_with_max_width(expr: &str, max_width: usize) -> String {
+    try_render_with_max_width(expr, max_width).unwrap_or_else(|_| expr.to_string())
+}
+
+//lossy version of try_render_ansi
+pub fn render_ansi(expr: &str) -> String {
+    try_render_ansi(expr).unwrap_or_else(|_| expr.to_string())
+}
+
+//lossy version of try_render_ansi_with
+pub fn render_ansi_with(expr: &str, options: &RenderOptions) -> String {
+    try_render_ansi_with(expr, options).unwrap_or_else(|_| expr.to_string())
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::glyph_set::GlyphSet;
+    use crate::latex::to_latex;
+    use crate::mathml::to_mathml;
+    use crate::render_options::{BracketStyle, MultiplicationSign};
+    use crate::rendered_formula::{layout_inline, InlineRun};
+    use crate::text_canvas::AmbiguousWidth;
+    use crate::typst::to_typst;
+
+    #[test]
+    fn test_try_render_unsupported() {
+        assert_eq!(
+            try_render("1 + cancel x"),
+            Err(RenderError::UnsupportedUnary {
+                op: "cancel".to_string(),
+                position: 4
+            })
+        );
+        assert_eq!(
+            try_render("x class(a)(x)"),
+            Err(RenderError::UnsupportedBinary {
+                op: "class".to_string(),
+                position: 2
+            })
+        );
+        assert_eq!(render("1 + cancel x"), "1 + cancel x");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(try_render("sin").unwrap(), "sin");
+        assert_eq!(try_render("sin x").unwrap(), "sin x");
+        assert_eq!(try_render("sin(x)").unwrap(), "sin(x)");
+        assert_eq!(try_render("f x").unwrap(), "fx");
+        assert_eq!(try_render("log_2 x").unwrap(), "log  x\n   2  ");
+        assert_eq!(try_render("sin^2 x").unwrap(), "   2  \nsin  x");
+        assert_eq!(try_render("sqrt sin x").unwrap(), "  ▁▁▁▁▁\n╲╱sin x");
+        assert_eq!(try_render("sin cos x").unwrap(), "sin cos x");
+    }
+
+    #[test]
+    fn test_big_operator_limits() {
+        assert_eq!(
+            try_render("sum_(n=0)^N n").unwrap(),
+            concat!(" N  \n", " ∑ n\n", "n=0 ")
+        );
+        assert_eq!(
+            try_render_with(
+                "sum_(n=0)^N n",
+                &RenderOptions {
+                    script_style: ScriptStyle::Inline,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            concat!(" N   \n", "∑   n\n", " n=0 ")
+        );
+        assert_eq!(try_render("lim_(x->0)").unwrap(), concat!("lim\n", "x→0"));
+        //only big operators take limits
+        assert_eq!(try_render("x_(n=0)").unwrap(), "x   \n n=0");
+    }
+
+    #[test]
+    fn test_stretchy_big_operators() {
+        assert_eq!(try_render("int x").unwrap(), "∫x");
+        assert_eq!(
+            try_render("int 1/x").unwrap(),
+            concat!("⌠ 1 \n", "⎮───\n", "⌡ x ")
+        );
+        assert_eq!(
+            try_render("sum_(n=1)^N 1/n^2").unwrap(),
+            concat!(
+                " N     \n",
+                "╲▔▔  1 \n",
+                " ╲ ────\n",
+                " ╱   2 \n",
+                "╱▁▁ n  \n",
+                "n=1    "
+            )
+        );
+        assert_eq!(
+            try_render("sum 1/2").unwrap(),
+            concat!("╲▔▔ 1 \n", " ⟩ ───\n", "╱▁▁ 2 ")
+        );
+        assert_eq!(
+            try_render("prod 1/2").unwrap(),
+            concat!("┬─┬ 1 \n", "│ │───\n", "│ │ 2 ")
+        );
+        //sized to the whole operand, up to next relation
+        assert_eq!(
+            try_render("int x 1/2 dx = y").unwrap(),
+            concat!("⌠  1     \n", "⎮x───dx=y\n", "⌡  2     ")
+        );
+    }
+
+    #[test]
+    fn test_font_commands() {
+        assert_eq!(try_render("bbb R").unwrap(), try_render("RR").unwrap());
+        assert_eq!(try_render("bb(x+1)").unwrap(), "𝐱+𝟏");
+        assert_eq!(try_render("cc L + fr g + sf x + tt y").unwrap(), "ℒ+𝔤+𝗑+𝚢");
+        assert_eq!(try_render("mathbb Z").unwrap(), "ℤ");
+        assert_eq!(try_render("bb alpha").unwrap(), "𝜶");
+        assert_eq!(try_render("cc(alpha x)").unwrap(), "α𝓍");
+        let error_fallback = RenderOptions {
+            font_fallback: FontFallback::Error,
+            ..Default::default()
+        };
+        assert_eq!(
+            try_render_with("cc(alpha x)", &error_fallback),
+            Err(RenderError::UnstyledCharacter {
+                text: "alpha".to_string(),
+                position: 3
+            })
+        );
+        assert_eq!(
+            try_render_with("bb(alpha+1)", &error_fallback).unwrap(),
+            "𝜶+𝟏"
+        );
+        assert_eq!(try_render("bb(sin x)").unwrap(), "𝐬𝐢𝐧 𝐱");
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(try_render("color(red)(x)+1").unwrap(), "x+1");
+        assert_eq!(
+            try_render_ansi("color(red)(x)+1").unwrap(),
+            "\x1b[31mx\x1b[0m+1"
+        );
+        assert_eq!(
+            try_render_ansi("color(#00ff00)(1/2)").unwrap(),
+            concat!(
+                "\x1b[38;2;0;255;0m 1 \x1b[0m\n",
+                "\x1b[38;2;0;255;0m───\x1b[0m\n",
+                "\x1b[38;2;0;255;0m 2 \x1b[0m"
+            )
+        );
+        assert_eq!(
+            try_render("color(nocolor)(x)"),
+            Err(RenderError::UnknownColor {
+                color: "nocolor".to_string(),
+                position: 6
+            })
+        );
+    }
+
+    #[test]
+    fn test_render_options() {
+        let options = RenderOptions {
+            operator_spacing: true,
+            multiplication_sign: MultiplicationSign::Cross,
+            ..Default::default()
+        };
+        assert_eq!(try_render_with("a+b=c*d", &options).unwrap(), "a + b = c×d");
+        assert_eq!(try_render("a+b=c*d").unwrap(), "a+b=c⋅d");
+        let options = RenderOptions {
+            fraction_overhang: 0,
+            bracket_style: BracketStyle::Simple,
+            ..Default::default()
+        };
+        assert_eq!(
+            try_render_with("(1/2)", &options).unwrap(),
+            concat!("(1)\n", "(─)\n", "(2)")
+        );
+    }
+
+    #[test]
+    fn test_glyph_sets() {
+        let options = RenderOptions {
+            glyphs: GlyphSet::conservative(),
+            ..Default::default()
+        };
+        assert_eq!(
+            try_render_with("<<1/2/3>>", &options).unwrap(),
+            concat!(" ╱ 1   ╲ \n", "< ───/3> \n", " ╲ 2   ╱ ")
+        );
+        let options = RenderOptions {
+            glyphs: GlyphSet::unicode().with_glyph("─", "━"),
+            ..Default::default()
+        };
+        assert_eq!(
+            try_render_with("1/2", &options).unwrap(),
+            concat!(" 1 \n", "━━━\n", " 2 ")
+        );
+    }
+
+    #[test]
+    fn test_output_formats_agree() {
+        //every example in tests.txt, and some that can't be rendered
+        let file = std::fs::read_to_string("tests.txt").unwrap();
+        let lines: Vec<&str> = file.lines().collect();
+        let mut exprs: Vec<&str> = lines
+            .windows(2)
+            .filter(|w| w[0].starts_with("##"))
+            .map(|w| w[1])
+            .collect();
+        exprs.extend([
+            "abs(x)",
+            "cancel x",
+            "text(ab)",
+            "class(a)(x)",
+            "color(nope)(x)",
+        ]);
+        for expr in exprs {
+            let rendered = try_render(expr).err();
+            assert_eq!(to_latex(expr).err(), rendered, "latex: {}", expr);
+            assert_eq!(to_mathml(expr).err(), rendered, "mathml: {}", expr);
+            assert_eq!(to_typst(expr).err(), rendered, "typst: {}", expr);
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let options = RenderOptions {
+            compact: true,
+            ..Default::default()
+        };
+        let compact = |expr| try_render_with(expr, &options).unwrap();
+        assert_eq!(compact("x^2 + a_1"), "x²+a₁");
+        assert_eq!(compact("(a+b)/(c) + 1/2"), "(a+b)/c+½");
+        assert_eq!(compact("x^(n+1) + x^(a/b)"), "xⁿ⁺¹+x^(a/b)");
+        assert_eq!(compact("sum_(n=0)^N sqrt(x+1)"), "∑ₙ₌₀ᴺ√(x+1)");
+        assert_eq!(
+            compact("[[1,2],[3,4]] + bar(AB)"),
+            "[[1,2],[3,4]]+A\u{304}B\u{304}"
+        );
+        //both scripts as script characters, or neither
+        assert_eq!(compact("x_b^2 + x_i^2"), "x_b^2+xᵢ²");
+        //converted nodes keep their colour
+        let options = RenderOptions {
+            compact: true,
+            ..Default::default()
+        };
+        let ansi = |expr| try_render_ansi_with(expr, &options).unwrap();
+        assert_eq!(ansi("color(red)(x)^2"), "\x1b[31mx\x1b[0m²");
+        assert_eq!(ansi("x^color(red)(2)"), "x\x1b[31m²\x1b[0m");
+        assert_eq!(ansi("color(red)(1/2)"), "\x1b[31m½\x1b[0m");
+        assert_eq!(ansi("color(red)(a)/b"), "\x1b[31ma\x1b[0m/b");
+    }
+
+    #[test]
+    fn test_max_width() {
+        assert_eq!(render_with_max_width("a+b+c+d+e+f", 11), "a+b+c+d+e+f");
+        assert_eq!(
+            render_with_max_width("a+b+c+d+e+f", 8),
+            concat!("a+b+c+d+\n", "  +e+f  ")
+        );
+        //every line is aligned on its own
+        assert_eq!(
+            render_with_max_width("x = 1/2 + y", 6),
+            concat!("   1  \n", "x=───+\n", "   2  \n", "  +y  ")
+        );
+        //nowhere to break, so compact style is used
+        assert_eq!(render_with_max_width("abcdefg+1/2", 5), "abcdefg+½");
+    }
+
+    #[test]
+    fn test_ascii_charset() {
+        let options = RenderOptions {
+            glyphs: GlyphSet::ascii(),
+            ..Default::default()
+        };
+        assert_eq!(
+            try_render_with("alpha <= 1/2 * x", &options).unwrap(),
+            concat!("        1   \n", "alpha<=---*x\n", "        2   ")
+        );
+        assert_eq!(
+            try_render_with("sqrt 2 + (1/2)", &options).unwrap(),
+            concat!("  _ / 1 \\\n", "\\/2+|---|\n", "    \\ 2 /")
+        );
+        assert_eq!(
+            try_render_with("bb R + bar x", &options).unwrap(),
+            "  -\nR+x"
+        );
+        //radical extended above its symbol for tall radicand
+        assert_eq!(
+            try_render_with("root 3 (1/2)", &options).unwrap(),
+            concat!("    ___\n", "   / 1 \n", " 3/ ---\n", "\\/   2 ")
+        );
+        //word operators are kept apart from their operands
+        assert_eq!(try_render_with("x in RR", &options).unwrap(), "x in RR");
+        assert_eq!(
+            try_render_with("AA x EE y", &options).unwrap(),
+            " AA x EE y"
+        );
+    }
+
+    #[test]
+    fn test_wide_graphemes() {
+        assert_eq!(
+            try_render("\"中文\"/x").unwrap(),
+            concat!(" 中文 \n", "──────\n", "   x  ")
+        );
+        assert_eq!(
+            try_render("+-/2").unwrap(),
+            concat!(" ± \n", "───\n", " 2 ")
+        );
+        assert_eq!(
+            try_render_with(
+                "+-/2",
+                &RenderOptions {
+                    ambiguous_width: AmbiguousWidth::Wide,
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+            concat!(" ± \n", "────\n", "  2 ")
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        //every node is measured once, so this renders instantly
+        let mut expr = "x".to_string();
+        for _ in 0..100 {
+            expr = format!("1/(1+{})", expr);
+        }
+        let rendered = try_render(&expr).unwrap();
+        assert_eq!(rendered.lines().count(), 201);
+    }
+
+    #[test]
+    fn test_try_render_incomplete_input() {
+        for input in [
+            "", "sin", "sqrt", "frac 1", "root", "x^", "x_", "()", "a()", "(1/)", "/",
+        ] {
+            assert!(try_render(input).is_ok(), "failed to render {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_baseline() {
+        assert_eq!(try_render_formula("x").unwrap().baseline(), 0);
+        assert_eq!(try_render_formula("x^2").unwrap().baseline(), 1);
+        assert_eq!(try_render_formula("1/2").unwrap().baseline(), 1);
+        assert_eq!(try_render_formula("sum_(n=0)^N n").unwrap().baseline(), 1);
+        let broken = try_render_formula_with(
+            "x^2+b+c/d",
+            &RenderOptions {
+                max_width: Some(6),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            broken.lines(),
+            vec![" 2    ", "x +b+ ", "    c ", "  +───", "    d "]
+        );
+        assert_eq!(broken.baseline(), 1);
+        let fraction = try_render_formula("x = 1/2").unwrap();
+        let line = layout_inline(&[
+            InlineRun::Text("where  "),
+            InlineRun::Formula(&fraction),
+            InlineRun::Text("  holds"),
+        ]);
+        assert_eq!(
+            line.text(),
+            concat!(
+                "          1        \n",
+                "where  x=───  holds\n",
+                "          2        "
+            )
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            try_render_html("a<b").unwrap(),
+            concat!(
+                "<pre class=\"am-formula\">",
+                "<span class=\"am-identifier\">a</span>",
+                "<span class=\"am-symbol\">&lt;</span>",
+                "<span class=\"am-identifier\">b</span></pre>"
+            )
+        );
+        assert_eq!(
+            try_render_html_with(
+                "color(#00ff00)(x)",
+                &RenderOptions::default(),
+                &HtmlOptions { titles: true }
+            )
+            .unwrap(),
+            concat!(
+                "<pre class=\"am-formula\">",
+                "<span class=\"am-identifier\" title=\"x\" style=\"color:#00ff00\">x</span></pre>"
+            )
+        );
+        assert_eq!(
+            render_html("cancel <x>"),
+            "<pre class=\"am-formula\">cancel &lt;x&gt;</pre>"
+        );
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = try_render_svg_with(
+            "x^2",
+            &RenderOptions::default(),
+            &SvgOptions {
+                cell_width: 8.0,
+                cell_height: 16.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(svg.contains("width=\"16\" height=\"32\""));
+        assert!(svg.contains("<text x=\"8\" y=\"8\" textLength=\"8\""));
+        assert!(svg.contains("<text x=\"0\" y=\"24\" textLength=\"8\""));
+        assert_eq!(svg.matches("<text").count(), 2);
+        assert!(render_svg("cancel x").contains(">c</text>"));
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let formula = try_render_formula("x^2+1/2").unwrap();
+        assert_eq!(formula.text(), concat!(" 2  1 \n", "x +───\n", "    2 "));
+        assert_eq!(formula.span_at(0, 1), Some(0..1));
+        assert_eq!(formula.span_at(1, 0), Some(2..3));
+        assert_eq!(formula.span_at(2, 1), Some(3..4));
+        //fraction bar belongs to the whole fraction
+        assert_eq!(formula.span_at(4, 1), Some(4..7));
+        assert_eq!(formula.span_at(2, 0), None);
+        assert_eq!(formula.cells_at(6), vec![(4, 2)]);
+        assert_eq!(
+            formula.cells_at(5),
+            vec![(3, 0), (5, 0), (3, 1), (4, 1), (5, 1), (3, 2), (5, 2)]
+        );
+        //script node covers both base and script
+        assert!(formula
+            .nodes()
+            .iter()
+            .any(|n| (n.span.clone(), n.x, n.y, n.width, n.height) == (0..3, 0, 0, 2, 2)));
+        //spans refer to the input, not to the rendered text
+        let formula = try_render_formula("sqrt(alpha)").unwrap();
+        assert_eq!(formula.span_at(2, 1), Some(5..10));
+        assert_eq!(
+            formula.cells_at(0),
+            vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1)]
+        );
     }
 }
@@ -0,0 +1,265 @@
+//Compact mode renders whole formula in a single line, for log lines,
+//table cells and such. Scripts use Unicode superscript and subscript
+//characters (x², aₙ) and fractions use vulgar fraction characters (½),
+//but only some characters have such forms, so when any of them is missing,
+//linear notation is used instead: x^(a/b), (a+b)/c
+//Functions here work on already rendered (single line) text of arguments
+//and return only the parts written in place of them, so that nodes
+//of arguments themselves (with their colours) can be kept.
+
+use crate::glyph_set::Charset;
+
+fn superscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' | '−' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'a' => 'ᵃ',
+        'b' => 'ᵇ',
+        'c' => 'ᶜ',
+        'd' => 'ᵈ',
+        'e' => 'ᵉ',
+        'f' => 'ᶠ',
+        'g' => 'ᵍ',
+        'h' => 'ʰ',
+        'i' => 'ⁱ',
+        'j' => 'ʲ',
+        'k' => 'ᵏ',
+        'l' => 'ˡ',
+        'm' => 'ᵐ',
+        'n' => 'ⁿ',
+        'o' => 'ᵒ',
+        'p' => 'ᵖ',
+        'r' => 'ʳ',
+        's' => 'ˢ',
+        't' => 'ᵗ',
+        'u' => 'ᵘ',
+        'v' => 'ᵛ',
+        'w' => 'ʷ',
+        'x' => 'ˣ',
+        'y' => 'ʸ',
+        'z' => 'ᶻ',
+        'A' => 'ᴬ',
+        'B' => 'ᴮ',
+        'D' => 'ᴰ',
+        'E' => 'ᴱ',
+        'G' => 'ᴳ',
+        'H' => 'ᴴ',
+        'I' => 'ᴵ',
+        'J' => 'ᴶ',
+        'K' => 'ᴷ',
+        'L' => 'ᴸ',
+        'M' => 'ᴹ',
+        'N' => 'ᴺ',
+        'O' => 'ᴼ',
+        'P' => 'ᴾ',
+        'R' => 'ᴿ',
+        'T' => 'ᵀ',
+        'U' => 'ᵁ',
+        'V' => 'ⱽ',
+        'W' => 'ᵂ',
+        'β' => 'ᵝ',
+        'γ' => 'ᵞ',
+        'δ' => 'ᵟ',
+        'θ' => 'ᶿ',
+        'φ' | 'ϕ' => 'ᵠ',
+        'χ' => 'ᵡ',
+        _ => return None,
+    })
+}
+
+fn subscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '₀',
+        '1' => '₁',
+        '2' => '₂',
+        '3' => '₃',
+        '4' => '₄',
+        '5' => '₅',
+        '6' => '₆',
+        '7' => '₇',
+        '8' => '₈',
+        '9' => '₉',
+        '+' => '₊',
+        '-' | '−' => '₋',
+        '=' => '₌',
+        '(' => '₍',
+        ')' => '₎',
+        'a' => 'ₐ',
+        'e' => 'ₑ',
+        'h' => 'ₕ',
+        'i' => 'ᵢ',
+        'j' => 'ⱼ',
+        'k' => 'ₖ',
+        'l' => 'ₗ',
+        'm' => 'ₘ',
+        'n' => 'ₙ',
+        'o' => 'ₒ',
+        'p' => 'ₚ',
+        'r' => 'ᵣ',
+        's' => 'ₛ',
+        't' => 'ₜ',
+        'u' => 'ᵤ',
+        'v' => 'ᵥ',
+        'x' => 'ₓ',
+        'β' => 'ᵦ',
+        'γ' => 'ᵧ',
+        'ρ' => 'ᵨ',
+        'φ' | 'ϕ' => 'ᵩ',
+        'χ' => 'ᵪ',
+        _ => return None,
+    })
+}
+
+//text written with superscript characters, if all of them have one
+pub fn superscript(text: &str) -> Option<String> {
+    text.chars().map(superscript_char).collect()
+}
+
+//text written with subscript characters, if all of them have one
+pub fn subscript(text: &str) -> Option<String> {
+    text.chars().map(subscript_char).collect()
+}
+
+fn vulgar_fraction(numer: &str, denom: &str) -> Option<&'static str> {
+    Some(match (numer, denom) {
+        ("1", "2") => "½",
+        ("1", "3") => "⅓",
+        ("2", "3") => "⅔",
+        ("1", "4") => "¼",
+        ("3", "4") => "¾",
+        ("1", "5") => "⅕",
+        ("2", "5") => "⅖",
+        ("3", "5") => "⅗",
+        ("4", "5") => "⅘",
+        ("1", "6") => "⅙",
+        ("5", "6") => "⅚",
+        ("1", "7") => "⅐",
+        ("1", "8") => "⅛",
+        ("3", "8") => "⅜",
+        ("5", "8") => "⅝",
+        ("7", "8") => "⅞",
+        ("1", "9") => "⅑",
+        ("1", "10") => "⅒",
+        _ => return None,
+    })
+}
+
+//anything but number or identifier needs brackets in linear notation
+pub fn needs_brackets(text: &str) -> bool {
+    text.is_empty() || !text.chars().all(|c| c.is_alphanumeric())
+}
+
+//(subscript, superscript) written with script characters: x_b^2 → xᵦ²,
+//None when either of them can't be, so that both are written as x_b^2
+pub fn scripts(
+    sub: Option<&str>,
+    sup: Option<&str>,
+    charset: Charset,
+) -> Option<(Option<String>, Option<String>)> {
+    if charset != Charset::Unicode {
+        return None;
+    }
+    let sub = match sub {
+        Some(sub) => Some(subscript(sub)?),
+        None => None,
+    };
+    let sup = match sup {
+        Some(sup) => Some(superscript(sup)?),
+        None => None,
+    };
+    Some((sub, sup))
+}
+
+//½ or ¹⁷⁄₂₃, None for fractions that need linear notation
+pub fn number_fraction(numer: &str, denom: &str, charset: Charset) -> Option<String> {
+    if charset != Charset::Unicode {
+        return None;
+    }
+    if let Some(f) = vulgar_fraction(numer, denom) {
+        return Some(f.to_string());
+    }
+    let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    if is_number(numer) && is_number(denom) {
+        Some(format!("{}⁄{}", superscript(numer)?, subscript(denom)?))
+    } else {
+        None
+    }
+}
+
+//√, or None when sqrt(...) has to be written
+pub fn sqrt_sign(charset: Charset) -> Option<&'static str> {
+    match charset {
+        Charset::Unicode => Some("√"),
+        Charset::Ascii => None,
+    }
+}
+
+//∛, ⁵√, or None when root(...)(...) has to be written
+pub fn radical(index: &str, charset: Charset) -> Option<String> {
+    match (charset, index) {
+        (Charset::Unicode, "3") => Some("∛".to_string()),
+        (Charset::Unicode, "4") => Some("∜".to_string()),
+        (Charset::Unicode, _) => superscript(index).map(|i| format!("{}√", i)),
+        (Charset::Ascii, _) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_scripts() {
+        assert_eq!(
+            scripts(None, Some("2"), Charset::Unicode),
+            Some((None, Some("²".to_string())))
+        );
+        assert_eq!(
+            scripts(Some("n+1"), None, Charset::Unicode),
+            Some((Some("ₙ₊₁".to_string()), None))
+        );
+        assert_eq!(scripts(None, Some("a/b"), Charset::Unicode), None);
+        //both scripts or neither
+        assert_eq!(scripts(Some("b"), Some("2"), Charset::Unicode), None);
+        assert_eq!(scripts(None, Some("2"), Charset::Ascii), None);
+    }
+
+    #[test]
+    fn test_fraction() {
+        assert_eq!(
+            number_fraction("1", "2", Charset::Unicode).as_deref(),
+            Some("½")
+        );
+        assert_eq!(
+            number_fraction("17", "23", Charset::Unicode).as_deref(),
+            Some("¹⁷⁄₂₃")
+        );
+        assert_eq!(number_fraction("a+b", "c", Charset::Unicode), None);
+        assert_eq!(number_fraction("1", "2", Charset::Ascii), None);
+        assert!(needs_brackets("a+b"));
+        assert!(!needs_brackets("c"));
+    }
+
+    #[test]
+    fn test_roots() {
+        assert_eq!(sqrt_sign(Charset::Unicode), Some("√"));
+        assert_eq!(radical("3", Charset::Unicode).as_deref(), Some("∛"));
+        assert_eq!(radical("n", Charset::Unicode).as_deref(), Some("ⁿ√"));
+        assert_eq!(radical("a/b", Charset::Unicode), None);
+        assert_eq!(radical("3", Charset::Ascii), None);
+    }
+}
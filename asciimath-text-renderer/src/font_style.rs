@@ -0,0 +1,149 @@
+//Font commands (bb, bbb, cc, tt, fr, sf) can't change terminal font,
+//but Unicode has Mathematical Alphanumeric Symbols block with whole
+//styled alphabets: 𝐀 𝔸 𝒜 𝙰 𝔄 𝖠
+//Some letters were encoded earlier in Letterlike Symbols block (ℝ, ℋ, ℭ, ...)
+//and are missing from the alphanumeric block, so they are listed separately.
+//Greek letters have styled forms only in bold.
+//Characters that have no styled form (punctuation, greek letters in other
+//fonts, ...) are left as they are, unless FontFallback says otherwise.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontStyle {
+    Bold,         //bb, mathbf
+    DoubleStruck, //bbb, mathbb
+    Script,       //cc, mathcal
+    Monospace,    //tt, mathtt
+    Fraktur,      //fr, mathfrak
+    SansSerif,    //sf, mathsf
+}
+
+//what to do with a letter or digit that has no styled form, ie. "cc alpha"
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FontFallback {
+    //write it unstyled, next to styled characters: cc(alpha x) → α𝓍
+    #[default]
+    Plain,
+    //fail with RenderError::UnstyledCharacter
+    Error,
+}
+
+impl FontStyle {
+    pub fn from_command(command: &str) -> Option<FontStyle> {
+        match command {
+            "bb" | "mathbf" => Some(FontStyle::Bold),
+            "bbb" | "mathbb" => Some(FontStyle::DoubleStruck),
+            "cc" | "mathcal" => Some(FontStyle::Script),
+            "tt" | "mathtt" => Some(FontStyle::Monospace),
+            "fr" | "mathfrak" => Some(FontStyle::Fraktur),
+            "sf" | "mathsf" => Some(FontStyle::SansSerif),
+            _ => None,
+        }
+    }
+
+    //first code point of (uppercase, lowercase, digits) alphabets
+    fn alphabet_starts(&self) -> (u32, u32, Option<u32>) {
+        match self {
+            FontStyle::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+            FontStyle::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+            FontStyle::Script => (0x1D49C, 0x1D4B6, None),
+            FontStyle::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+            FontStyle::Fraktur => (0x1D504, 0x1D51E, None),
+            FontStyle::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+        }
+    }
+
+    //first code point of (uppercase, lowercase) greek alphabets.
+    //Lowercase greek is italic in formulas (as in TeX \boldsymbol),
+    //so bold lowercase comes from bold italic alphabet: 𝚨 𝜶
+    fn greek_starts(&self) -> Option<(u32, u32)> {
+        match self {
+            FontStyle::Bold => Some((0x1D6A8, 0x1D736)),
+            _ => None,
+        }
+    }
+
+    //letters that live in Letterlike Symbols block
+    fn letterlike(&self, c: char) -> Option<char> {
+        match (self, c) {
+            (FontStyle::DoubleStruck, 'C') => Some('ℂ'),
+            (FontStyle::DoubleStruck, 'H') => Some('ℍ'),
+            (FontStyle::DoubleStruck, 'N') => Some('ℕ'),
+            (FontStyle::DoubleStruck, 'P') => Some('ℙ'),
+            (FontStyle::DoubleStruck, 'Q') => Some('ℚ'),
+            (FontStyle::DoubleStruck, 'R') => Some('ℝ'),
+            (FontStyle::DoubleStruck, 'Z') => Some('ℤ'),
+            (FontStyle::Script, 'B') => Some('ℬ'),
+            (FontStyle::Script, 'E') => Some('ℰ'),
+            (FontStyle::Script, 'F') => Some('ℱ'),
+            (FontStyle::Script, 'H') => Some('ℋ'),
+            (FontStyle::Script, 'I') => Some('ℐ'),
+            (FontStyle::Script, 'L') => Some('ℒ'),
+            (FontStyle::Script, 'M') => Some('ℳ'),
+            (FontStyle::Script, 'R') => Some('ℛ'),
+            (FontStyle::Script, 'e') => Some('ℯ'),
+            (FontStyle::Script, 'g') => Some('ℊ'),
+            (FontStyle::Script, 'o') => Some('ℴ'),
+            (FontStyle::Fraktur, 'C') => Some('ℭ'),
+            (FontStyle::Fraktur, 'H') => Some('ℌ'),
+            (FontStyle::Fraktur, 'I') => Some('ℑ'),
+            (FontStyle::Fraktur, 'R') => Some('ℜ'),
+            (FontStyle::Fraktur, 'Z') => Some('ℨ'),
+            _ => None,
+        }
+    }
+
+    //styled form of a character, if there is one
+    pub fn styled_char(&self, c: char) -> Option<char> {
+        if let Some(l) = self.letterlike(c) {
+            return Some(l);
+        }
+        let (upper, lower, digits) = self.alphabet_starts();
+        let code_point = match c {
+            'A'..='Z' => upper + (c as u32 - 'A' as u32),
+            'a'..='z' => lower + (c as u32 - 'a' as u32),
+            '0'..='9' => digits? + (c as u32 - '0' as u32),
+            'Α'..='Ω' => self.greek_starts()?.0 + (c as u32 - 'Α' as u32),
+            'α'..='ω' => self.greek_starts()?.1 + (c as u32 - 'α' as u32),
+            _ => return None,
+        };
+        char::from_u32(code_point)
+    }
+
+    //style every character that has styled form, keep the rest unchanged
+    pub fn apply(&self, text: &str) -> String {
+        text.chars()
+            .map(|c| self.styled_char(c).unwrap_or(c))
+            .collect()
+    }
+
+    //first letter or digit in text that has no styled form
+    pub fn unstyled_char(&self, text: &str) -> Option<char> {
+        text.chars()
+            .find(|c| c.is_alphanumeric() && self.styled_char(*c).is_none())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_font_style() {
+        assert_eq!(FontStyle::Bold.apply("Ab1"), "𝐀𝐛𝟏");
+        assert_eq!(FontStyle::DoubleStruck.apply("RA"), "ℝ𝔸");
+        assert_eq!(FontStyle::Script.apply("AHe"), "𝒜ℋℯ");
+        assert_eq!(FontStyle::Monospace.apply("A"), "𝙰");
+        assert_eq!(FontStyle::Fraktur.apply("AC"), "𝔄ℭ");
+        assert_eq!(FontStyle::SansSerif.apply("A"), "𝖠");
+        assert_eq!(FontStyle::Bold.apply("Ωαως"), "𝛀𝜶𝝎𝝇");
+    }
+
+    #[test]
+    fn test_font_style_fallback() {
+        assert_eq!(FontStyle::Script.apply("x1+α"), "𝓍1+α");
+        assert_eq!(FontStyle::Fraktur.apply("2"), "2");
+        assert_eq!(FontStyle::Script.unstyled_char("x+α"), Some('α'));
+        assert_eq!(FontStyle::Bold.unstyled_char("x+α"), None);
+    }
+}
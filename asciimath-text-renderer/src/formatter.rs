@@ -0,0 +1,597 @@
+//Formatter writing asciimath in one consistent way, for keeping formulas
+//in documentation and source files tidy:
+//  frac 1 2+{:x:}^2<=y  ->  1/2+x^2 <= y
+//Parsed tree is written back with spaces only where they are needed
+//to keep tokens apart, fractions in preferred notation and without
+//invisible brackets ({: :}) that don't group anything.
+//Relations (also ones with scripts, like "=^def") are spaced if wanted,
+//at every nesting level, in brackets and scripts just like at top level:
+//  sum_(i=1)^n  ->  sum_(i = 1)^n
+//No other items get spaces they don't need, so "int f(x) dx" is "int f(x)dx".
+//Formatted expression renders exactly as the original one.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use asciimath_parser::{Token, Tokenizer};
+
+use crate::asciimath::{raw_argument, try_render, BIG_OPERATORS, RELATIONS};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FractionStyle {
+    //1/2
+    #[default]
+    Slash,
+    //frac 1 2
+    Frac,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub fraction_style: FractionStyle,
+    //a <= b instead of a<=b, at every nesting level
+    pub relation_spacing: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            fraction_style: FractionStyle::Slash,
+            relation_spacing: true,
+        }
+    }
+}
+
+//one writing of the whole expression
+struct Writer<'s> {
+    //formatted string, needed to copy text as it was written
+    source: &'s str,
+    options: &'s FormatOptions,
+    //whether to change fraction notation and unwrap invisible brackets,
+    //which changes number of items
+    normalize: bool,
+    //expressions already written, by their address in parsed tree,
+    //as fraction parts are written for every notation that is tried
+    exprs: RefCell<HashMap<usize, String>>,
+}
+
+fn tokens(text: &str) -> Vec<(&str, Token)> {
+    Tokenizer::new(text).collect()
+}
+
+//strict: words are always separated ("frac a b"),
+//otherwise only when either of them is longer than a letter ("2x", but "alpha x"),
+//is a text, or the first one ends a script ("x^2 y")
+fn needs_space(left: &str, right: &str, strict: bool) -> bool {
+    let left_tokens = tokens(left);
+    let right_tokens = tokens(right);
+    let (Some(last), Some(first)) = (left_tokens.last(), right_tokens.first()) else {
+        return false;
+    };
+    let word = |(text, token): &(&str, Token)| {
+        *token == Token::Text || text.chars().all(char::is_alphanumeric)
+    };
+    let long_word = |(text, token): &(&str, Token)| {
+        *token == Token::Text || (text.chars().count() > 1 && text.chars().all(char::is_alphabetic))
+    };
+    let script = matches!(left_tokens[..], [.., (_, Token::Sub | Token::Super), _]);
+    if word(last) && word(first) && (strict || script || long_word(last) || long_word(first)) {
+        return true;
+    }
+    //tokens that would merge into another one, like "-" and ">"
+    let joined = format!("{}{}", left, right);
+    tokens(&joined) != [left_tokens, right_tokens].concat()
+}
+
+fn join(parts: &[&str], strict: bool) -> String {
+    let mut result = String::new();
+    for part in parts.iter().filter(|part| !part.is_empty()) {
+        if needs_space(&result, part, strict) {
+            result.push(' ');
+        }
+        result.push_str(part);
+    }
+    result
+}
+
+//comma separated items in brackets
+fn bracketed(left: &str, items: &[String], right: &str) -> String {
+    let mut parts = vec![left];
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            parts.push(",");
+        }
+        parts.push(item);
+    }
+    parts.push(right);
+    join(&parts, false)
+}
+
+//whether nothing after it can be taken as its argument
+fn is_closed(simple: &asciimath_parser::tree::Simple) -> bool {
+    match simple {
+        asciimath_parser::tree::Simple::Missing => false,
+        asciimath_parser::tree::Simple::Unary(unary) => is_closed(unary.arg()),
+        asciimath_parser::tree::Simple::Func(func) => is_closed(func.arg()),
+        asciimath_parser::tree::Simple::Binary(binary) => {
+            is_closed(binary.first()) && is_closed(binary.second())
+        }
+        _ => true,
+    }
+}
+
+//content of brackets, when they don't group anything: "{:x:}"
+fn group_content<'a, 'b>(
+    group: &'b asciimath_parser::tree::Group<'a>,
+) -> Option<&'b asciimath_parser::tree::Simple<'a>> {
+    if group.expr.len() != 1 {
+        return None;
+    }
+    let asciimath_parser::tree::Intermediate::ScriptFunc(
+        asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+            simple,
+            script: asciimath_parser::tree::Script::None,
+        }),
+    ) = &group.expr[0]
+    else {
+        return None;
+    };
+    let keeps_meaning = match simple {
+        //big operators get their limits and size only when they are not in a group
+        asciimath_parser::tree::Simple::Symbol(symbol) => {
+            !BIG_OPERATORS.contains(symbol) && matches!(tokens(symbol)[..], [(_, Token::Symbol)])
+        }
+        asciimath_parser::tree::Simple::Ident(ident) => {
+            matches!(tokens(ident)[..], [(_, Token::Ident)])
+        }
+        //function in a group doesn't take what follows it as argument
+        asciimath_parser::tree::Simple::Func(_) => false,
+        //brackets of operator argument are not shown, so "sqrt{:(x):}" is not "sqrt(x)"
+        asciimath_parser::tree::Simple::Group(_) => false,
+        simple => is_closed(simple),
+    };
+    if keeps_meaning {
+        Some(simple)
+    } else {
+        None
+    }
+}
+
+//argument of "text", written as it was
+fn raw_text(simple: &asciimath_parser::tree::Simple, source: &str) -> String {
+    match simple {
+        asciimath_parser::tree::Simple::Group(group) => format!(
+            "{}{}{}",
+            group.left_bracket,
+            raw_argument(simple, source),
+            group.right_bracket
+        ),
+        asciimath_parser::tree::Simple::Text(text) => format!("\"{}\"", text),
+        simple => raw_argument(simple, source).to_string(),
+    }
+}
+
+fn format_simple(simple: &asciimath_parser::tree::Simple, writer: &Writer) -> String {
+    let format = |simple| format_simple(simple, writer);
+    match simple {
+        asciimath_parser::tree::Simple::Missing => String::new(),
+        asciimath_parser::tree::Simple::Number(s)
+        | asciimath_parser::tree::Simple::Ident(s)
+        | asciimath_parser::tree::Simple::Symbol(s) => s.to_string(),
+        asciimath_parser::tree::Simple::Text(text) => format!("\"{}\"", text),
+        asciimath_parser::tree::Simple::Unary(unary) => {
+            let arg = if matches!(unary.op, "text" | "mbox") {
+                raw_text(unary.arg(), writer.source)
+            } else {
+                format(unary.arg())
+            };
+            join(&[unary.op, &arg], true)
+        }
+        asciimath_parser::tree::Simple::Func(func) => join(&[func.func, &format(func.arg())], true),
+        asciimath_parser::tree::Simple::Binary(binary) => join(
+            &[binary.op, &format(binary.first()), &format(binary.second())],
+            true,
+        ),
+        asciimath_parser::tree::Simple::Group(group) => match group_content(group) {
+            Some(simple) if group.left_bracket == "{:" && group.right_bracket == ":}" => {
+                format(simple)
+            }
+            _ => bracketed(
+                group.left_bracket,
+                &[format_expr(&group.expr, writer)],
+                group.right_bracket,
+            ),
+        },
+        asciimath_parser::tree::Simple::Matrix(matrix) => {
+            //every row is written in the same brackets as the whole matrix
+            let rows: Vec<String> = matrix
+                .rows()
+                .map(|row| {
+                    let cells: Vec<String> = row.iter().map(|e| format_expr(e, writer)).collect();
+                    bracketed(matrix.left_bracket, &cells, matrix.right_bracket)
+                })
+                .collect();
+            bracketed(matrix.left_bracket, &rows, matrix.right_bracket)
+        }
+    }
+}
+
+fn format_script(base: String, script: &asciimath_parser::tree::Script, writer: &Writer) -> String {
+    let sub = script.sub().map(|s| format_simple(s, writer));
+    let sup = script.sup().map(|s| format_simple(s, writer));
+    let mut parts = vec![base.as_str()];
+    if let Some(sub) = &sub {
+        parts.extend(["_", sub]);
+    }
+    if let Some(sup) = &sup {
+        parts.extend(["^", sup]);
+    }
+    join(&parts, false)
+}
+
+fn format_script_func(script_func: &asciimath_parser::tree::ScriptFunc, writer: &Writer) -> String {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => format_script(
+            format_simple(&simple_script.simple, writer),
+            &simple_script.script,
+            writer,
+        ),
+        asciimath_parser::tree::ScriptFunc::Func(func) => join(
+            &[
+                &format_script(func.func.to_string(), &func.script, writer),
+                &format_script_func(func.arg(), writer),
+            ],
+            true,
+        ),
+    }
+}
+
+//numerator or denominator, without brackets around single simple,
+//as they are not shown anyway: "(a)/b", "frac{a}{b}"
+fn fraction_part(simple: &asciimath_parser::tree::Simple, writer: &Writer) -> String {
+    match simple {
+        asciimath_parser::tree::Simple::Group(group)
+            if matches!(
+                (group.left_bracket, group.right_bracket),
+                ("(", ")") | ("{", "}") | ("{:", ":}")
+            ) =>
+        {
+            let simple = group_content(group).unwrap_or(simple);
+            format_simple(simple, writer)
+        }
+        simple => format_simple(simple, writer),
+    }
+}
+
+fn fraction_script_func(
+    script_func: &asciimath_parser::tree::ScriptFunc,
+    writer: &Writer,
+) -> String {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+            simple,
+            script: asciimath_parser::tree::Script::None,
+        }) => fraction_part(simple, writer),
+        script_func => format_script_func(script_func, writer),
+    }
+}
+
+//part of "a/b" written as argument of "frac", in parentheses unless it is a single simple
+fn frac_argument(script_func: &asciimath_parser::tree::ScriptFunc, writer: &Writer) -> String {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+            simple,
+            script: asciimath_parser::tree::Script::None,
+        }) if is_closed(simple) && !matches!(simple, asciimath_parser::tree::Simple::Func(_)) => {
+            fraction_part(simple, writer)
+        }
+        script_func => bracketed("(", &[format_script_func(script_func, writer)], ")"),
+    }
+}
+
+fn is_missing(script_func: &asciimath_parser::tree::ScriptFunc) -> bool {
+    matches!(
+        script_func,
+        asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+            simple: asciimath_parser::tree::Simple::Missing,
+            ..
+        })
+    )
+}
+
+fn single_script_func<'a, 'b>(
+    expr: &'b asciimath_parser::tree::Expression<'a>,
+) -> Option<&'b asciimath_parser::tree::ScriptFunc<'a>> {
+    match expr.iter().collect::<Vec<_>>()[..] {
+        [asciimath_parser::tree::Intermediate::ScriptFunc(script_func)] => Some(script_func),
+        _ => None,
+    }
+}
+
+//"numer/denom", if it's read back as fraction of these two
+//(it's not when they are brackets, like "|")
+fn slash_fraction(numer: &str, denom: &str) -> Option<String> {
+    let text = join(&[numer, "/", denom], false);
+    let parsed = asciimath_parser::parse(&text);
+    let (numer, denom) = (
+        asciimath_parser::parse(numer),
+        asciimath_parser::parse(denom),
+    );
+    match parsed.iter().collect::<Vec<_>>()[..] {
+        [asciimath_parser::tree::Intermediate::Frac(fraction)]
+            if single_script_func(&numer) == Some(&fraction.numer)
+                && single_script_func(&denom) == Some(&fraction.denom) =>
+        {
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+//"frac numer denom", if it's read back as fraction of these two
+fn frac_command(numer: &str, denom: &str) -> Option<String> {
+    let text = join(&["frac", numer, denom], true);
+    let parsed = asciimath_parser::parse(&text);
+    let (numer, denom) = (
+        asciimath_parser::parse(numer),
+        asciimath_parser::parse(denom),
+    );
+    let simple = |expr| match single_script_func(expr) {
+        Some(asciimath_parser::tree::ScriptFunc::Simple(
+            asciimath_parser::tree::SimpleScript {
+                simple,
+                script: asciimath_parser::tree::Script::None,
+            },
+        )) => Some(simple),
+        _ => None,
+    };
+    match simple(&parsed) {
+        Some(asciimath_parser::tree::Simple::Binary(binary))
+            if binary.op == "frac"
+                && simple(&numer) == Some(binary.first())
+                && simple(&denom) == Some(binary.second()) =>
+        {
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+//expression items as text, with flag telling which ones are relations
+fn format_items(expr: &asciimath_parser::tree::Expression, writer: &Writer) -> Vec<(String, bool)> {
+    let (normalize, options) = (writer.normalize, writer.options);
+    let mut items = vec![];
+    for intermediate in expr.iter() {
+        match intermediate {
+            asciimath_parser::tree::Intermediate::ScriptFunc(
+                asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+                    simple,
+                    script: asciimath_parser::tree::Script::None,
+                }),
+            ) => match simple {
+                asciimath_parser::tree::Simple::Group(group)
+                    if normalize && group.left_bracket == "{:" && group.right_bracket == ":}" =>
+                {
+                    items.extend(format_items(&group.expr, writer))
+                }
+                asciimath_parser::tree::Simple::Group(group) if !normalize => items.push((
+                    bracketed(
+                        group.left_bracket,
+                        &[format_expr(&group.expr, writer)],
+                        group.right_bracket,
+                    ),
+                    false,
+                )),
+                asciimath_parser::tree::Simple::Binary(binary)
+                    if normalize && binary.op == "frac" =>
+                {
+                    let numer = fraction_part(binary.first(), writer);
+                    let denom = fraction_part(binary.second(), writer);
+                    let slash = options.fraction_style == FractionStyle::Slash
+                        && [binary.first(), binary.second()].iter().all(|arg| {
+                            is_closed(arg)
+                                && !matches!(arg, asciimath_parser::tree::Simple::Func(_))
+                        });
+                    let text = if slash {
+                        slash_fraction(&numer, &denom)
+                    } else {
+                        frac_command(&numer, &denom)
+                    };
+                    items.push((text.unwrap_or_else(|| format_simple(simple, writer)), false));
+                }
+                asciimath_parser::tree::Simple::Symbol(symbol) => {
+                    items.push((symbol.to_string(), RELATIONS.contains(symbol)))
+                }
+                simple => items.push((format_simple(simple, writer), false)),
+            },
+            asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+                //relation with scripts: "=^def"
+                let relation = matches!(
+                    script_func,
+                    asciimath_parser::tree::ScriptFunc::Simple(asciimath_parser::tree::SimpleScript {
+                        simple: asciimath_parser::tree::Simple::Symbol(symbol),
+                        ..
+                    }) if RELATIONS.contains(symbol)
+                );
+                items.push((format_script_func(script_func, writer), relation))
+            }
+            asciimath_parser::tree::Intermediate::Frac(fraction) => {
+                let frac = if normalize
+                    && options.fraction_style == FractionStyle::Frac
+                    && !is_missing(&fraction.numer)
+                    && !is_missing(&fraction.denom)
+                {
+                    frac_command(
+                        &frac_argument(&fraction.numer, writer),
+                        &frac_argument(&fraction.denom, writer),
+                    )
+                } else {
+                    None
+                };
+                let text = frac.or_else(|| {
+                    slash_fraction(
+                        &fraction_script_func(&fraction.numer, writer),
+                        &fraction_script_func(&fraction.denom, writer),
+                    )
+                });
+                let text = text.unwrap_or_else(|| {
+                    let numer = format_script_func(&fraction.numer, writer);
+                    let denom = format_script_func(&fraction.denom, writer);
+                    join(&[&numer, "/", &denom], false)
+                });
+                items.push((text, false));
+            }
+        }
+    }
+    items
+}
+
+fn join_items(items: &[(String, bool)], options: &FormatOptions) -> String {
+    let mut result = String::new();
+    let mut previous_relation = false;
+    for (text, relation) in items.iter().filter(|(text, _)| !text.is_empty()) {
+        let spaced = options.relation_spacing && (*relation || previous_relation);
+        if !result.is_empty() && (spaced || needs_space(&result, text, false)) {
+            result.push(' ');
+        }
+        result.push_str(text);
+        previous_relation = *relation;
+    }
+    result
+}
+
+fn format_expr(expr: &asciimath_parser::tree::Expression, writer: &Writer) -> String {
+    let key = expr as *const _ as usize;
+    if let Some(text) = writer.exprs.borrow().get(&key) {
+        return text.clone();
+    }
+    let text = join_items(&format_items(expr, writer), writer.options);
+    writer.exprs.borrow_mut().insert(key, text.clone());
+    text
+}
+
+pub fn format_asciimath(expr: &str) -> String {
+    format_asciimath_with(expr, &FormatOptions::default())
+}
+
+pub fn format_asciimath_with(expr: &str, options: &FormatOptions) -> String {
+    let parsed = asciimath_parser::parse(expr);
+    let write = |normalize| {
+        let writer = Writer {
+            source: expr,
+            options,
+            normalize,
+            exprs: RefCell::default(),
+        };
+        format_expr(&parsed, &writer)
+    };
+    let (normalized, plain) = (write(true), write(false));
+    //items taken out of brackets could bind to their neighbours ("{:sin:} x")
+    //or be aligned differently than the whole group, and big operators
+    //stretch to the height of the group next to them,
+    //so expression is written as it was unless it looks the same
+    //(and it can't be told when either of them doesn't render)
+    let same_rendering = match (try_render(&normalized), try_render(&plain)) {
+        (Ok(normalized), Ok(plain)) => normalized == plain,
+        _ => false,
+    };
+    if normalized == plain || same_rendering {
+        normalized
+    } else {
+        plain
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::asciimath::render;
+    use std::fs::read_to_string;
+
+    #[test]
+    fn test_format_asciimath() {
+        assert_eq!(format_asciimath("x^2 + 2 x+1"), "x^2+2x+1");
+        assert_eq!(format_asciimath("a<=b"), "a <= b");
+        assert_eq!(format_asciimath("frac 1 2"), "1/2");
+        assert_eq!(format_asciimath("frac(a+b)(c)"), "(a+b)/c");
+        assert_eq!(format_asciimath("(x)/(y)^2"), "x/(y)^2");
+        assert_eq!(format_asciimath("frac{1}{2}"), "1/2");
+        assert_eq!(format_asciimath("{:a:}/{b}"), "a/b");
+        assert_eq!(format_asciimath("frac{a+b}{2}"), "{a+b}/2");
+        assert_eq!(format_asciimath("{:x:}^{:2:} + {: a b :}"), "x^2+ab");
+        assert_eq!(format_asciimath("sqrt {: x :}"), "sqrt x");
+        assert_eq!(format_asciimath("sin x alpha beta"), "sin x alpha beta");
+        //relations are spaced at top level and in brackets and scripts alike,
+        //other items only where tokens would merge
+        assert_eq!(format_asciimath("x>0"), "x > 0");
+        assert_eq!(format_asciimath("a =^def b"), "a =^d ef b");
+        assert_eq!(format_asciimath("sum_(i=1)^n i"), "sum_(i = 1)^n i");
+        assert_eq!(format_asciimath("{x|x>0}"), "{x|x > 0}");
+        assert_eq!(format_asciimath("x^{a=b}"), "x^{a = b}");
+        assert_eq!(format_asciimath("int_0^1 f(x) dx"), "int_0^1 f(x)dx");
+        assert_eq!(format_asciimath("{:sin:} x"), "{:sin:}x");
+        assert_eq!(format_asciimath("{:sum:}_a"), "{:sum:}_a");
+        assert_eq!(format_asciimath("- >"), "- >");
+        assert_eq!(
+            format_asciimath("text( a  b ) \"c\" x"),
+            "text( a  b )\"c\" x"
+        );
+        assert_eq!(format_asciimath("[[1, 2], [3, 4]]"), "[[1,2],[3,4]]");
+        let options = FormatOptions {
+            fraction_style: FractionStyle::Frac,
+            relation_spacing: false,
+        };
+        assert_eq!(format_asciimath_with("y = 1/2", &options), "y=frac 1 2");
+        assert_eq!(
+            format_asciimath_with("(a+b)/x^2", &options),
+            "frac(a+b)(x^2)"
+        );
+        assert_eq!(format_asciimath_with("frac{1}{2}", &options), "frac 1 2");
+        assert_eq!(
+            format_asciimath_with("sum_(i = 1)^n", &options),
+            "sum_(i=1)^n"
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        //every expression is written once, so this formats instantly
+        let (mut expr, mut expected) = ("x".to_string(), "x".to_string());
+        for _ in 0..50 {
+            expr = format!("frac 1 (1 + {})", expr);
+            expected = format!("1/(1+{})", expected);
+        }
+        assert_eq!(format_asciimath(&expr), expected);
+    }
+
+    //formatting doesn't change how expressions look, and formatted ones stay as they are
+    #[test]
+    fn test_format_examples_from_test_file() {
+        let text = read_to_string("tests.txt").unwrap();
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            if !line.starts_with("##") {
+                continue;
+            }
+            let expr = lines.next().unwrap();
+            for options in [
+                FormatOptions::default(),
+                FormatOptions {
+                    fraction_style: FractionStyle::Frac,
+                    relation_spacing: false,
+                },
+            ] {
+                let formatted = format_asciimath_with(expr, &options);
+                assert_eq!(
+                    render(&formatted),
+                    render(expr),
+                    "{} -> {}",
+                    expr,
+                    formatted
+                );
+                assert_eq!(format_asciimath_with(&formatted, &options), formatted);
+            }
+        }
+    }
+}
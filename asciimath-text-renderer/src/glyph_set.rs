@@ -0,0 +1,107 @@
+//Glyphs drawn by renderer (brackets, fraction bars, radicals, big operators)
+//come from several Unicode blocks, and not every font has all of them.
+//GlyphSet maps glyph renderer would draw to one that should be drawn instead,
+//glyphs missing from the table are drawn as they are.
+//Substitute must be one column wide, like every drawn glyph,
+//or layout breaks.
+
+use std::collections::HashMap;
+
+//characters formula text can use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Charset {
+    #[default]
+    Unicode,
+    //for consoles and logs that can't show anything else.
+    //Symbols are written as in asciimath (α → alpha, ≤ → <=),
+    //font commands and combining accents are not used.
+    Ascii,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GlyphSet {
+    charset: Charset,
+    substitutes: HashMap<String, String>,
+}
+
+impl GlyphSet {
+    //every glyph as designed
+    pub fn unicode() -> Self {
+        GlyphSet::default()
+    }
+
+    //avoids glyphs from rarely supported blocks:
+    //Symbols for Legacy Computing (🮤 🮥) and Halfwidth Forms (￨)
+    pub fn conservative() -> Self {
+        GlyphSet::default()
+            .with_glyph("🮤", "<")
+            .with_glyph("🮥", ">")
+            .with_glyph("￨", "|")
+    }
+
+    //ASCII replacement for every glyph, brackets are drawn with / | \
+    pub fn ascii() -> Self {
+        let mut glyph_set = GlyphSet {
+            charset: Charset::Ascii,
+            substitutes: HashMap::new(),
+        };
+        for (glyphs, substitute) in [
+            (&["⎛", "⎠", "⎧", "⎭", "⎰", "╱", "⌠", "⌡"][..], "/"),
+            (&["⎝", "⎞", "⎩", "⎫", "⎱", "╲"], "\\"),
+            (&["⎜", "⎥", "⎪", "￨", "│", "⎮"], "|"),
+            (&["⎡", "⎣"], "["),
+            (&["⎤", "⎦"], "]"),
+            (&["⎨", "⟨", "🮤"], "<"),
+            (&["⎬", "⟩", "🮥", "→"], ">"),
+            (&["─", "‾", "▔"], "-"),
+            (&["▁"], "_"),
+            (&["┬"], "+"),
+            (&["˜"], "~"),
+            (&["˙"], "."),
+            (&["¨"], "\""),
+            (&["∑"], "E"),
+            (&["∏"], "P"),
+            (&["∫"], "S"),
+        ] {
+            for glyph in glyphs {
+                glyph_set = glyph_set.with_glyph(glyph, substitute);
+            }
+        }
+        glyph_set
+    }
+
+    //override single glyph, ie. GlyphSet::unicode().with_glyph("─", "━")
+    pub fn with_glyph(mut self, glyph: &str, substitute: &str) -> Self {
+        self.substitutes
+            .insert(glyph.to_string(), substitute.to_string());
+        self
+    }
+
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    //glyph that should be drawn in place of given one
+    pub fn get<'a>(&'a self, glyph: &'a str) -> &'a str {
+        self.substitutes
+            .get(glyph)
+            .map(|s| s.as_str())
+            .unwrap_or(glyph)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_glyph_set() {
+        assert_eq!(GlyphSet::unicode().get("🮤"), "🮤");
+        assert_eq!(GlyphSet::conservative().get("🮤"), "<");
+        assert_eq!(GlyphSet::conservative().get("⎛"), "⎛");
+        assert_eq!(GlyphSet::ascii().get("⎛"), "/");
+        assert_eq!(GlyphSet::ascii().with_glyph("─", "=").get("─"), "=");
+        assert_eq!(GlyphSet::ascii().charset(), Charset::Ascii);
+    }
+}
@@ -0,0 +1,163 @@
+//HTML output, for publishing formulas in web pages and reports.
+//Formula is written as <pre>, with runs of cells drawn by the same node
+//wrapped in spans with classes telling node kind and colour:
+//  <span class="am-fraction am-red" title="1/2">───</span>
+//(colours given in hex notation are set with style attribute instead).
+//Non-ASCII graphemes are put in inline blocks exactly one or two cells wide,
+//so that glyphs missing from the font, taken from some fallback font,
+//don't break alignment of a monospace font.
+
+use crate::text_canvas::{CellStyle, Color, NodeBox, TextCanvas};
+
+//default look of formulas, to be included in the page
+pub const HTML_STYLE: &str = "\
+pre.am-formula { line-height: 1; }
+.am-black { color: black; }
+.am-red { color: red; }
+.am-green { color: green; }
+.am-yellow { color: #b8860b; }
+.am-blue { color: blue; }
+.am-magenta { color: magenta; }
+.am-cyan { color: darkcyan; }
+.am-white { color: white; }
+.am-gray { color: gray; }
+";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    //add title attributes with part of the input node came from
+    pub titles: bool,
+}
+
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn open_tag(
+    node: Option<&NodeBox>,
+    style: CellStyle,
+    source: &str,
+    options: &HtmlOptions,
+) -> String {
+    let mut classes = vec![];
+    let mut attributes = String::new();
+    if let Some(node) = node {
+        classes.push(format!("am-{}", node.kind));
+        if let (true, Some(snippet)) = (options.titles, source.get(node.span.clone())) {
+            attributes.push_str(&format!(" title=\"{}\"", escape(snippet)));
+        }
+    }
+    match style.color {
+        Some(Color::Rgb(r, g, b)) => {
+            attributes.push_str(&format!(" style=\"color:#{:02x}{:02x}{:02x}\"", r, g, b))
+        }
+        Some(color) => classes.extend(color.name().map(|name| format!("am-{}", name))),
+        None => {}
+    }
+    if classes.is_empty() {
+        format!("<span{}>", attributes)
+    } else {
+        format!("<span class=\"{}\"{}>", classes.join(" "), attributes)
+    }
+}
+
+//source is the rendered input, used for titles
+pub fn canvas_to_html(canvas: &TextCanvas, source: &str, options: &HtmlOptions) -> String {
+    let mut result = String::from("<pre class=\"am-formula\">");
+    for y in 0..canvas.height {
+        if y > 0 {
+            result.push('\n');
+        }
+        let key = |x: usize| {
+            (
+                canvas.node_box_at(x, y).map(|n| n as *const NodeBox),
+                canvas.get_style(x, y),
+            )
+        };
+        let mut x = 0;
+        while x < canvas.width {
+            //run of cells drawn by the same node, with the same style
+            let run_key = key(x);
+            let end = (x..canvas.width)
+                .find(|&x| key(x) != run_key)
+                .unwrap_or(canvas.width);
+            let node = canvas.node_box_at(x, y);
+            let style = canvas.get_style(x, y);
+            let styled = node.is_some() || style != CellStyle::default();
+            if styled {
+                result.push_str(&open_tag(node, style, source, options));
+            }
+            for x in x..end {
+                let grapheme = canvas.get(x, y);
+                if grapheme.is_empty() {
+                    //second half of wide grapheme
+                } else if grapheme.is_ascii() {
+                    result.push_str(&escape(grapheme));
+                } else {
+                    let width = if x + 1 < canvas.width && canvas.get(x + 1, y).is_empty() {
+                        2
+                    } else {
+                        1
+                    };
+                    result.push_str(&format!(
+                        "<span style=\"display:inline-block;width:{}ch\">{}</span>",
+                        width,
+                        escape(grapheme)
+                    ));
+                }
+            }
+            if styled {
+                result.push_str("</span>");
+            }
+            x = end;
+        }
+    }
+    result.push_str("</pre>");
+    result
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn test_canvas_to_html() {
+        let mut tc = TextCanvas::new(3, 1);
+        tc.set(0, 0, "<");
+        tc.set_wide(1, 0, "面");
+        tc.add_node_box(NodeBox {
+            span: 0..1,
+            kind: "symbol",
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        });
+        assert_eq!(
+            canvas_to_html(&tc, "<", &HtmlOptions { titles: true }),
+            concat!(
+                "<pre class=\"am-formula\">",
+                "<span class=\"am-symbol\" title=\"&lt;\">&lt;</span>",
+                "<span style=\"display:inline-block;width:2ch\">面</span>",
+                "</pre>"
+            )
+        );
+    }
+}
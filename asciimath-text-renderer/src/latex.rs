@@ -0,0 +1,461 @@
+//LaTeX export, for handing formulas to people who use LaTeX.
+//Walks the same asciimath_parser tree as visitors in asciimath module,
+//but writes LaTeX source instead of building renderer nodes:
+//  sum_(n=1)^N 1/n^2  ->  \sum_{n = 1}^{N} \frac{1}{n^{2}}
+//Output needs amsmath and amssymb (and xcolor for colours).
+//Symbols without LaTeX command are written as Unicode characters
+//(which works with unicode-math).
+
+use phf::{phf_map, phf_set};
+
+use crate::asciimath::{check_binary, check_unary, position, raw_argument, RenderError, SYMBOLS};
+use crate::text_canvas::Color;
+
+//asciimath symbols that are not just a backslash away from their LaTeX command
+//(alphabetic symbols not listed here, like "alpha" or "leq", are)
+static LATEX_SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
+    "epsi" => "\\epsilon",
+    "lamda" => "\\lambda",
+    "Lamda" => "\\Lambda",
+
+    "*" => "\\cdot",
+    "**" => "\\ast",
+    "***" => "\\star",
+    "//" => "/",
+    "\\\\" => "\\backslash",
+    "xx" => "\\times",
+    "|><" => "\\ltimes",
+    "><|" => "\\rtimes",
+    "|><|" => "\\bowtie",
+    "-:" => "\\div",
+    "divide" => "\\div",
+    "@" => "\\circ",
+    "o+" => "\\oplus",
+    "ox" => "\\otimes",
+    "o." => "\\odot",
+    "^^" => "\\wedge",
+    "^^^" => "\\bigwedge",
+    "vv" => "\\vee",
+    "vvv" => "\\bigvee",
+    "nn" => "\\cap",
+    "nnn" => "\\bigcap",
+    "uu" => "\\cup",
+    "uuu" => "\\bigcup",
+
+    "=" => "=",
+    "!=" => "\\ne",
+    ":=" => ":=",
+    "<" => "<",
+    "lt" => "<",
+    "<=" => "\\le",
+    "lt=" => "\\le",
+    ">" => ">",
+    "gt" => ">",
+    "mlt" => "\\ll",
+    ">=" => "\\ge",
+    "gt=" => "\\ge",
+    "mgt" => "\\gg",
+    "-<" => "\\prec",
+    "-lt" => "\\prec",
+    ">-" => "\\succ",
+    "-<=" => "\\preceq",
+    ">-=" => "\\succeq",
+    "!in" => "\\notin",
+    "sub" => "\\subset",
+    "sup" => "\\supset",
+    "sube" => "\\subseteq",
+    "supe" => "\\supseteq",
+    "-=" => "\\equiv",
+    "~=" => "\\cong",
+    "~~" => "\\approx",
+    "aprox" => "\\approx",
+    "~" => "\\sim",
+    "prop" => "\\propto",
+
+    "and" => "\\text{and}",
+    "or" => "\\text{or}",
+    "if" => "\\text{if}",
+    "not" => "\\neg",
+    "=>" => "\\Rightarrow",
+    "<=>" => "\\Leftrightarrow",
+    "AA" => "\\forall",
+    "EE" => "\\exists",
+    "_|_" => "\\bot",
+    "TT" => "\\top",
+    "|--" => "\\vdash",
+    "|==" => "\\models",
+
+    ":|:" => "\\mid",
+    "del" => "\\partial",
+    "grad" => "\\nabla",
+    "+-" => "\\pm",
+    "-+" => "\\mp",
+    "O/" => "\\emptyset",
+    "oo" => "\\infty",
+    "..." => "\\ldots",
+    ":." => "\\therefore",
+    ":'" => "\\because",
+    "/_" => "\\angle",
+    "/_\\" => "\\triangle",
+    "'" => "'",
+    "tilde" => "\\sim",
+    "\\ " => "\\ ",
+    "|__" => "\\lfloor",
+    "__|" => "\\rfloor",
+    "|~" => "\\lceil",
+    "~|" => "\\rceil",
+    "lceiling" => "\\lceil",
+    "rceiling" => "\\rceil",
+    "CC" => "\\mathbb{C}",
+    "NN" => "\\mathbb{N}",
+    "QQ" => "\\mathbb{Q}",
+    "RR" => "\\mathbb{R}",
+    "ZZ" => "\\mathbb{Z}",
+
+    "Lim" => "\\operatorname*{Lim}",
+    "lub" => "\\operatorname*{lub}",
+    "glb" => "\\operatorname*{glb}",
+    "mod" => "\\bmod",
+
+    "uarr" => "\\uparrow",
+    "darr" => "\\downarrow",
+    "rarr" => "\\rightarrow",
+    "->" => "\\to",
+    ">->" => "\\rightarrowtail",
+    "->>" => "\\twoheadrightarrow",
+    "|->" => "\\mapsto",
+    "larr" => "\\leftarrow",
+    "harr" => "\\leftrightarrow",
+    "rArr" => "\\Rightarrow",
+    "lArr" => "\\Leftarrow",
+    "hArr" => "\\Leftrightarrow",
+};
+
+//functions that have LaTeX command, others are written with \operatorname
+static LATEX_FUNCTIONS: phf::Set<&'static str> = phf_set! {
+    "sin", "cos", "tan", "sinh", "cosh", "tanh", "cot", "sec", "csc", "arcsin", "arccos",
+    "arctan", "coth", "exp", "log", "ln", "det", "gcd",
+};
+
+//characters with special meaning in LaTeX
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\\' => result.push_str("\\backslash "),
+            '~' => result.push_str("\\sim "),
+            '^' => result.push_str("\\hat{}"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+//text mode (\text{...}) has its own way of writing some characters
+fn escape_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\\' => result.push_str("\\textbackslash{}"),
+            '~' => result.push_str("\\textasciitilde{}"),
+            '^' => result.push_str("\\textasciicircum{}"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn symbol(symbol: &str) -> String {
+    if let Some(s) = LATEX_SYMBOLS.get(symbol) {
+        s.to_string()
+    } else if symbol.chars().all(|c| c.is_ascii_alphabetic()) {
+        //"alpha", "leq", "sum", ...
+        format!("\\{}", symbol)
+    } else {
+        escape(SYMBOLS.get(symbol).unwrap_or(&symbol))
+    }
+}
+
+fn bracket(bracket: &str) -> &'static str {
+    match bracket {
+        "(" | "left(" => "(",
+        ")" | "right)" => ")",
+        "[" | "left[" => "[",
+        "]" | "right]" => "]",
+        "{" => "\\{",
+        "}" => "\\}",
+        "|" | "|:" | ":|" => "|",
+        "(:" | "<<" | "langle" => "\\langle",
+        ":)" | ">>" | "rangle" => "\\rangle",
+        _ => ".",
+    }
+}
+
+fn function_name(func: &str) -> String {
+    if LATEX_FUNCTIONS.contains(func) {
+        format!("\\{}", func)
+    } else if func.chars().count() > 1 {
+        format!("\\operatorname{{{}}}", func)
+    } else {
+        func.to_string()
+    }
+}
+
+//colour argument of \textcolor, basic colours are known to xcolor by name
+fn text_color(color: Color) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("[HTML]{{{:02X}{:02X}{:02X}}}", r, g, b),
+        color => format!("{{{}}}", color.name().unwrap_or_default()),
+    }
+}
+
+//source is the converted string, used to report error positions
+pub fn latex_simple(
+    simple: &asciimath_parser::tree::Simple,
+    omit_braces: bool,
+    source: &str,
+) -> Result<String, RenderError> {
+    let arg = |simple| latex_simple(simple, true, source);
+    match simple {
+        asciimath_parser::tree::Simple::Missing => Ok(String::new()),
+        asciimath_parser::tree::Simple::Number(number) => Ok(number.to_string()),
+        asciimath_parser::tree::Simple::Text(text) => {
+            Ok(format!("\\text{{{}}}", escape_text(text)))
+        }
+        asciimath_parser::tree::Simple::Ident(ident) => Ok(escape(ident)),
+        asciimath_parser::tree::Simple::Symbol(s) => Ok(symbol(s)),
+        asciimath_parser::tree::Simple::Unary(unary) => {
+            check_unary(unary.op, source)?;
+            let command = match unary.op {
+                "sqrt" => "sqrt",
+                "hat" => "hat",
+                "bar" | "overline" => "overline",
+                "vec" => "vec",
+                "tilde" => "tilde",
+                "dot" => "dot",
+                "ddot" => "ddot",
+                "ul" | "underline" => "underline",
+                "bb" | "mathbf" => "mathbf",
+                "bbb" | "mathbb" => "mathbb",
+                "cc" | "mathcal" => "mathcal",
+                "tt" | "mathtt" => "mathtt",
+                "fr" | "mathfrak" => "mathfrak",
+                "sf" | "mathsf" => "mathsf",
+                op => {
+                    return Err(RenderError::UnsupportedUnary {
+                        op: op.to_string(),
+                        position: position(source, op),
+                    })
+                }
+            };
+            Ok(format!("\\{}{{{}}}", command, arg(unary.arg())?))
+        }
+        asciimath_parser::tree::Simple::Func(func) => {
+            //function in simple context (ie. "sqrt sin x") can't have scripts
+            let name = function_name(func.func);
+            match latex_simple(func.arg(), false, source)? {
+                arg if arg.is_empty() => Ok(name),
+                arg => Ok(format!("{} {}", name, arg)),
+            }
+        }
+        asciimath_parser::tree::Simple::Binary(binary) => {
+            check_binary(binary.op, source)?;
+            let first = arg(binary.first())?;
+            let second = arg(binary.second())?;
+            match binary.op {
+                "frac" => Ok(format!("\\frac{{{}}}{{{}}}", first, second)),
+                "root" => Ok(format!("\\sqrt[{}]{{{}}}", first, second)),
+                "stackrel" => Ok(format!("\\stackrel{{{}}}{{{}}}", first, second)),
+                "color" => {
+                    let color_name = raw_argument(binary.first(), source);
+                    let color =
+                        Color::from_name(color_name).ok_or_else(|| RenderError::UnknownColor {
+                            color: color_name.to_string(),
+                            position: position(source, color_name),
+                        })?;
+                    Ok(format!("\\textcolor{}{{{}}}", text_color(color), second))
+                }
+                op => Err(RenderError::UnsupportedBinary {
+                    op: op.to_string(),
+                    position: position(source, op),
+                }),
+            }
+        }
+        asciimath_parser::tree::Simple::Group(group) => {
+            let expr = latex_expr(&group.expr, source)?;
+            let (left, right) = (bracket(group.left_bracket), bracket(group.right_bracket));
+            if omit_braces {
+                Ok(expr)
+            } else if left == "." && right == "." {
+                Ok(format!("{{{}}}", expr))
+            } else {
+                Ok(format!("\\left{} {} \\right{}", left, expr, right))
+            }
+        }
+        asciimath_parser::tree::Simple::Matrix(matrix) => {
+            let mut rows = vec![];
+            for row in matrix.rows() {
+                let cells = row
+                    .iter()
+                    .map(|e| latex_expr(e, source))
+                    .collect::<Result<Vec<String>, RenderError>>()?;
+                rows.push(cells.join(" & "));
+            }
+            let (left, right) = (bracket(matrix.left_bracket), bracket(matrix.right_bracket));
+            let environment = match (left, right) {
+                ("(", ")") => "pmatrix",
+                ("[", "]") => "bmatrix",
+                ("\\{", "\\}") => "Bmatrix",
+                ("|", "|") => "vmatrix",
+                (".", ".") => "matrix",
+                _ => "",
+            };
+            if environment.is_empty() {
+                Ok(format!(
+                    "\\left{} \\begin{{matrix}} {} \\end{{matrix}} \\right{}",
+                    left,
+                    rows.join(" \\\\ "),
+                    right
+                ))
+            } else {
+                Ok(format!(
+                    "\\begin{{{}}} {} \\end{{{}}}",
+                    environment,
+                    rows.join(" \\\\ "),
+                    environment
+                ))
+            }
+        }
+    }
+}
+
+pub fn latex_script(
+    base: String,
+    script: &asciimath_parser::tree::Script,
+    source: &str,
+) -> Result<String, RenderError> {
+    let mut result = base;
+    if let Some(sub) = script.sub() {
+        result.push_str(&format!("_{{{}}}", latex_simple(sub, true, source)?));
+    }
+    if let Some(sup) = script.sup() {
+        result.push_str(&format!("^{{{}}}", latex_simple(sup, true, source)?));
+    }
+    Ok(result)
+}
+
+pub fn latex_script_func(
+    script_func: &asciimath_parser::tree::ScriptFunc,
+    omit_braces: bool,
+    source: &str,
+) -> Result<String, RenderError> {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => latex_script(
+            latex_simple(&simple_script.simple, omit_braces, source)?,
+            &simple_script.script,
+            source,
+        ),
+        asciimath_parser::tree::ScriptFunc::Func(func) => {
+            //scripts belong to function name, ie. "sin^2 x"
+            let name = latex_script(function_name(func.func), &func.script, source)?;
+            match latex_script_func(func.arg(), false, source)? {
+                arg if arg.is_empty() => Ok(name),
+                arg => Ok(format!("{} {}", name, arg)),
+            }
+        }
+    }
+}
+
+pub fn latex_intermediate(
+    intermediate: &asciimath_parser::tree::Intermediate,
+    source: &str,
+) -> Result<String, RenderError> {
+    match intermediate {
+        asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+            latex_script_func(script_func, false, source)
+        }
+        asciimath_parser::tree::Intermediate::Frac(fraction) => Ok(format!(
+            "\\frac{{{}}}{{{}}}",
+            latex_script_func(&fraction.numer, true, source)?,
+            latex_script_func(&fraction.denom, true, source)?
+        )),
+    }
+}
+
+pub fn latex_expr(
+    expr: &asciimath_parser::tree::Expression,
+    source: &str,
+) -> Result<String, RenderError> {
+    let items = expr
+        .iter()
+        .map(|e| latex_intermediate(e, source))
+        .collect::<Result<Vec<String>, RenderError>>()?;
+    Ok(items
+        .into_iter()
+        .filter(|item| !item.is_empty())
+        .collect::<Vec<String>>()
+        .join(" "))
+}
+
+pub fn to_latex(expr: &str) -> Result<String, RenderError> {
+    latex_expr(&asciimath_parser::parse(expr), expr)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::asciimath::try_render;
+
+    #[test]
+    fn test_to_latex() {
+        assert_eq!(to_latex("x^2+1").unwrap(), "x^{2} + 1");
+        assert_eq!(to_latex("1/2").unwrap(), "\\frac{1}{2}");
+        assert_eq!(to_latex("(a+b)/c").unwrap(), "\\frac{a + b}{c}");
+        assert_eq!(to_latex("root 3 x").unwrap(), "\\sqrt[3]{x}");
+        assert_eq!(to_latex("sqrt(x+1)").unwrap(), "\\sqrt{x + 1}");
+        assert_eq!(to_latex("sum_(n=1)^N n").unwrap(), "\\sum_{n = 1}^{N} n");
+        assert_eq!(to_latex("alpha <= beta").unwrap(), "\\alpha \\le \\beta");
+        assert_eq!(
+            to_latex("bbb R xx bb x").unwrap(),
+            "\\mathbb{R} \\times \\mathbf{x}"
+        );
+        assert_eq!(to_latex("(x)").unwrap(), "\\left( x \\right)");
+        assert_eq!(to_latex("{: x :}").unwrap(), "{x}");
+        assert_eq!(
+            to_latex("(:x:)").unwrap(),
+            "\\left\\langle x \\right\\rangle"
+        );
+        assert_eq!(
+            to_latex("[[a,b],[c,d]]").unwrap(),
+            "\\begin{bmatrix} a & b \\\\ c & d \\end{bmatrix}"
+        );
+        assert_eq!(to_latex("sin^2 x").unwrap(), "\\sin^{2} x");
+        assert_eq!(to_latex("sech x").unwrap(), "\\operatorname{sech} x");
+        assert_eq!(
+            to_latex("lim_(x->0) f(x)").unwrap(),
+            "\\lim_{x \\to 0} f \\left( x \\right)"
+        );
+        assert_eq!(
+            to_latex("\"a_b\" \"100%\"").unwrap(),
+            "\\text{a\\_b} \\text{100\\%}"
+        );
+        assert_eq!(to_latex("color(red)(x)").unwrap(), "\\textcolor{red}{x}");
+        assert_eq!(
+            to_latex("color(#ff8000)(x)").unwrap(),
+            "\\textcolor[HTML]{FF8000}{x}"
+        );
+        assert!(to_latex("color(nope)(x)").is_err());
+        //constructs text renderer can't draw are rejected the same way
+        assert_eq!(to_latex("abs(x)"), Err(try_render("abs(x)").unwrap_err()));
+        assert!(to_latex("cancel x").is_err());
+    }
+}
@@ -1,5 +1,35 @@
 mod asciimath;
+mod compact;
+mod font_style;
+mod formatter;
+mod glyph_set;
+mod html;
+mod latex;
+mod line_break;
+mod markdown;
+mod mathml;
+mod render_options;
+mod rendered_formula;
 mod renderer;
+mod svg;
 mod text_canvas;
+mod typst;
 
-pub use asciimath::render;
+pub use asciimath::{
+    render, render_ansi, render_ansi_with, render_html, render_svg, render_with,
+    render_with_max_width, try_render, try_render_ansi, try_render_ansi_with, try_render_formula,
+    try_render_formula_with, try_render_html, try_render_html_with, try_render_svg,
+    try_render_svg_with, try_render_with, try_render_with_max_width, RenderError,
+};
+pub use font_style::FontFallback;
+pub use formatter::{format_asciimath, format_asciimath_with, FormatOptions, FractionStyle};
+pub use glyph_set::{Charset, GlyphSet};
+pub use html::{HtmlOptions, HTML_STYLE};
+pub use latex::to_latex;
+pub use markdown::{render_markdown, render_markdown_with, render_markdown_with_errors};
+pub use mathml::to_mathml;
+pub use render_options::{BracketStyle, MultiplicationSign, RenderOptions, ScriptStyle};
+pub use rendered_formula::{layout_inline, InlineRun, RenderedFormula};
+pub use svg::SvgOptions;
+pub use text_canvas::{AmbiguousWidth, NodeBox};
+pub use typst::to_typst;
@@ -0,0 +1,86 @@
+//Breaking formula that is too wide for the terminal into several lines.
+//Only top level row of the formula is broken, at relation and additive
+//operators (break points). Operator ends the line and is repeated
+//at the beginning of the next one, which is indented:
+//  a+b+c+d  with max width 6 becomes:  a+b+
+//                                        +c+d
+//Every line is laid out on its own (like Expr), so it stays aligned.
+
+use crate::glyph_set::GlyphSet;
+use crate::renderer::Drawable;
+use crate::text_canvas::TextCanvas;
+
+pub const CONTINUATION_INDENT: usize = 2;
+
+//top level node, with a flag telling if it's a break point
+pub type RowItem = (Box<dyn Drawable>, bool);
+
+//Returns canvas and level (baseline row) of its first line,
+//or None when some part between break points doesn't fit.
+pub fn break_lines(
+    items: &[RowItem],
+    max_width: usize,
+    glyphs: &GlyphSet,
+) -> Option<(TextCanvas, usize)> {
+    //segments start with break point operator (except the first one)
+    let mut segments: Vec<Vec<&dyn Drawable>> = vec![];
+    for (idx, (item, is_break)) in items.iter().enumerate() {
+        if segments.is_empty() || (*is_break && idx > 0) {
+            segments.push(vec![]);
+        }
+        segments.last_mut().unwrap().push(&**item);
+    }
+    let width = |nodes: &[&dyn Drawable]| nodes.iter().map(|n| n.width()).sum::<usize>();
+
+    let mut lines: Vec<(usize, Vec<&dyn Drawable>)> = vec![(0, vec![])];
+    for (idx, segment) in segments.iter().enumerate() {
+        //there must be room left for the operator that may end the line
+        let trailing = segments.get(idx + 1).map(|s| s[0].width()).unwrap_or(0);
+        let (indent, line) = lines.last_mut().unwrap();
+        if *indent + width(line) + width(segment) + trailing <= max_width {
+            line.extend(segment.iter().copied());
+            continue;
+        }
+        if line.is_empty() {
+            return None;
+        }
+        line.push(segment[0]);
+        if CONTINUATION_INDENT + width(segment) + trailing > max_width {
+            return None;
+        }
+        lines.push((CONTINUATION_INDENT, segment.clone()));
+    }
+
+    //(level, height) of every line, as Expr computes it
+    let boxes: Vec<(usize, usize)> = lines
+        .iter()
+        .map(|(_, line)| {
+            let level = line.iter().map(|n| n.level()).max().unwrap_or(0);
+            let height = line
+                .iter()
+                .map(|n| (level + n.height()).saturating_sub(n.level() + 1) + 1)
+                .max()
+                .unwrap_or(0);
+            (level, height)
+        })
+        .collect();
+    let mut canvas = TextCanvas::new(
+        lines
+            .iter()
+            .map(|(indent, line)| indent + width(line))
+            .max()
+            .unwrap_or(0),
+        boxes.iter().map(|(_, height)| height).sum(),
+    );
+    let baseline = boxes.first().map(|(level, _)| *level).unwrap_or(0);
+    let mut y = 0;
+    for ((indent, line), (level, height)) in lines.iter().zip(boxes) {
+        let mut x = *indent;
+        for node in line {
+            node.draw(&mut canvas, glyphs, x, y + level - node.level());
+            x += node.width();
+        }
+        y += height;
+    }
+    Some((canvas, baseline))
+}
@@ -1,9 +1,419 @@
 pub mod asciimath;
+pub mod compact;
+pub mod font_style;
+pub mod formatter;
+pub mod glyph_set;
+pub mod html;
+pub mod latex;
+pub mod line_break;
+pub mod markdown;
+pub mod mathml;
+pub mod render_options;
+pub mod rendered_formula;
 pub mod renderer;
+pub mod svg;
 pub mod text_canvas;
+pub mod typst;
+
+use std::io::{IsTerminal, Read, Write};
+
+use formatter::{FormatOptions, FractionStyle};
+use glyph_set::GlyphSet;
+use html::HtmlOptions;
+use render_options::RenderOptions;
+use svg::SvgOptions;
+
+const USAGE: &str = "\
+usage: amr [OPTIONS] [EXPRESSION...]
+       amr md [OPTIONS] [FILE...]
+       amr fmt [FMT OPTIONS] [FILE...]
+
+Renders asciimath expressions given as arguments, or read from files
+or stdin (one per line) when there are none.
+md renders formulas embedded in Markdown documents (stdin by default).
+fmt writes expressions from files (one per line, stdin by default)
+in consistent asciimath notation.
+
+options:
+  -f, --file FILE       read expressions from FILE (- for stdin), can be repeated
+  -p, --paragraphs      expressions in files are separated by blank lines,
+                        so they can span several lines
+  --trim                remove trailing spaces from rendered lines
+  --indent N            indent rendered lines with N spaces
+  --separator TEXT      print TEXT in a line between rendered expressions
+  --format FORMAT       output format: text, html, svg, latex, mathml, typst
+  --debug-tree          print parsed tree instead of rendering
+  --ascii               use only ASCII characters
+  --glyphs SET          glyphs to draw with: unicode, conservative, ascii
+  --compact             render every expression in a single line
+  --max-width N         break expressions wider than N columns
+  -h, --help            print this message
+
+--ascii, --glyphs, --compact and --max-width apply to text, html
+and svg formats only.
+
+fmt options:
+  --check               only list expressions that are not formatted
+  --frac                write fractions as frac a b instead of a/b
+  --tight-relations     don't put spaces around relations
+
+exit status: 0 when everything was rendered, 1 when some expression
+(or formula in md) has constructs that are not supported (it's printed
+as it is) or, with fmt --check, is not formatted,
+2 for invalid arguments and 3 when input cannot be read.";
+
+const EXIT_UNSUPPORTED: i32 = 1;
+const EXIT_UNFORMATTED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Format {
+    #[default]
+    Text,
+    Html,
+    Svg,
+    Latex,
+    MathMl,
+    Typst,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    markdown: bool,
+    fmt: bool,
+    check: bool,
+    format_options: FormatOptions,
+    expressions: Vec<String>,
+    files: Vec<String>,
+    paragraphs: bool,
+    trim: bool,
+    indent: usize,
+    separator: Option<String>,
+    format: Format,
+    debug_tree: bool,
+    options: RenderOptions,
+    //options given that only fmt or text rendering takes, for error messages
+    fmt_flags: Vec<String>,
+    render_flags: Vec<String>,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("amr: {}", message);
+    eprintln!("try amr --help");
+    std::process::exit(EXIT_USAGE);
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    args.next()
+        .unwrap_or_else(|| usage_error(&format!("{} takes a value", flag)))
+}
+
+fn number(args: &mut impl Iterator<Item = String>, flag: &str) -> usize {
+    value(args, flag)
+        .parse()
+        .unwrap_or_else(|_| usage_error(&format!("{} takes a number", flag)))
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Args {
+    let mut parsed = Args::default();
+    let mut args = args.peekable();
+    match args.peek().map(|a| a.as_str()) {
+        Some("md") => parsed.markdown = true,
+        Some("fmt") => parsed.fmt = true,
+        _ => {}
+    }
+    if parsed.markdown || parsed.fmt {
+        args.next();
+    }
+    //positional arguments are expressions (or files for md and fmt)
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" | "--frac" | "--tight-relations" => parsed.fmt_flags.push(arg.clone()),
+            "--ascii" | "--glyphs" | "--compact" | "--max-width" => {
+                parsed.render_flags.push(arg.clone())
+            }
+            _ => {}
+        }
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            "-f" | "--file" => parsed.files.push(value(&mut args, &arg)),
+            "-p" | "--paragraphs" => parsed.paragraphs = true,
+            "--trim" => parsed.trim = true,
+            "--indent" => parsed.indent = number(&mut args, &arg),
+            "--separator" => parsed.separator = Some(value(&mut args, &arg)),
+            "--format" => {
+                parsed.format = match value(&mut args, &arg).as_str() {
+                    "text" => Format::Text,
+                    "html" => Format::Html,
+                    "svg" => Format::Svg,
+                    "latex" => Format::Latex,
+                    "mathml" => Format::MathMl,
+                    "typst" => Format::Typst,
+                    _ => {
+                        usage_error("--format takes one of: text, html, svg, latex, mathml, typst")
+                    }
+                }
+            }
+            "--debug-tree" => parsed.debug_tree = true,
+            "--check" => parsed.check = true,
+            "--frac" => parsed.format_options.fraction_style = FractionStyle::Frac,
+            "--tight-relations" => parsed.format_options.relation_spacing = false,
+            "--ascii" => parsed.options.glyphs = GlyphSet::ascii(),
+            "--compact" => parsed.options.compact = true,
+            "--max-width" => parsed.options.max_width = Some(number(&mut args, &arg)),
+            "--glyphs" => {
+                parsed.options.glyphs = match value(&mut args, &arg).as_str() {
+                    "unicode" => GlyphSet::unicode(),
+                    "conservative" => GlyphSet::conservative(),
+                    "ascii" => GlyphSet::ascii(),
+                    _ => usage_error("--glyphs takes one of: unicode, conservative, ascii"),
+                }
+            }
+            //anything after -- is positional, even if it looks like an option
+            "--" => positional.extend(args.by_ref()),
+            flag if flag.starts_with("--") => usage_error(&format!("unknown option: {}", flag)),
+            _ => positional.push(arg),
+        }
+    }
+    if parsed.markdown || parsed.fmt {
+        parsed.files.extend(positional);
+    } else {
+        parsed.expressions = positional;
+    }
+    if let (false, Some(flag)) = (parsed.fmt, parsed.fmt_flags.first()) {
+        usage_error(&format!("{} can only be used with fmt", flag));
+    }
+    if let (true, Some(flag)) = (parsed.fmt, parsed.render_flags.first()) {
+        usage_error(&format!("{} cannot be used with fmt", flag));
+    }
+    if let (Format::Latex | Format::MathMl | Format::Typst, Some(flag)) =
+        (parsed.format, parsed.render_flags.first())
+    {
+        usage_error(&format!(
+            "{} can only be used with text, html and svg formats",
+            flag
+        ));
+    }
+    parsed
+}
+
+fn read_input(file: &str) -> String {
+    let result = if file == "-" {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input).map(|_| input)
+    } else {
+        std::fs::read_to_string(file)
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("amr: cannot read {}: {}", file, e);
+        std::process::exit(EXIT_IO);
+    })
+}
+
+//expressions written one per line, or separated with blank lines
+fn split_expressions(input: &str, paragraphs: bool) -> Vec<String> {
+    if !paragraphs {
+        return input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string())
+            .collect();
+    }
+    let mut expressions = vec![];
+    let mut current: Vec<&str> = vec![];
+    for line in input.lines().chain([""]) {
+        if !line.trim().is_empty() {
+            current.push(line);
+        } else if !current.is_empty() {
+            expressions.push(current.join("\n"));
+            current.clear();
+        }
+    }
+    expressions
+}
+
+//formatted expressions, one per line, with blank lines and indentation kept
+fn format_lines(input: &str, options: &FormatOptions) -> String {
+    input
+        .split_inclusive('\n')
+        .map(|line| {
+            let text = line.trim_end_matches(['\r', '\n']);
+            let expr = text.trim();
+            if expr.is_empty() {
+                return line.to_string();
+            }
+            let indent = &text[..text.len() - text.trim_start().len()];
+            format!(
+                "{}{}{}",
+                indent,
+                formatter::format_asciimath_with(expr, options),
+                &line[text.len()..]
+            )
+        })
+        .collect()
+}
+
+//trimming and indentation of rendered lines
+fn format_output(rendered: &str, args: &Args) -> String {
+    let indent = " ".repeat(args.indent);
+    rendered
+        .lines()
+        .map(|line| {
+            let line = if args.trim { line.trim_end() } else { line };
+            format!("{}{}", indent, line)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+//output is often piped to less or head, which may quit before reading all of it
+fn write_output(text: &str, exit_code: i32) {
+    let mut stdout = std::io::stdout().lock();
+    if stdout.write_all(text.as_bytes()).is_err() {
+        std::process::exit(exit_code);
+    }
+}
 
 fn main() {
-    let arg = std::env::args().last().unwrap().to_string();
-    let rendered = asciimath::render(&arg);
-    println!("{}", rendered);
+    let mut args = parse_args(std::env::args().skip(1));
+    if args.expressions.is_empty() && args.files.is_empty() {
+        if std::io::stdin().is_terminal() {
+            usage_error("no expressions given");
+        }
+        args.files.push("-".to_string());
+    }
+    let inputs: Vec<String> = args.files.iter().map(|file| read_input(file)).collect();
+
+    if args.markdown {
+        let mut exit_code = 0;
+        for input in inputs {
+            let (rendered, errors) = markdown::render_markdown_with_errors(&input, &args.options);
+            for (formula, e) in errors {
+                eprintln!("amr: {}: {}", formula, e);
+                exit_code = EXIT_UNSUPPORTED;
+            }
+            write_output(&rendered, exit_code);
+        }
+        std::process::exit(exit_code);
+    }
+
+    if args.fmt {
+        let mut exit_code = 0;
+        for (file, input) in args.files.iter().zip(inputs) {
+            let formatted = format_lines(&input, &args.format_options);
+            if !args.check {
+                write_output(&formatted, exit_code);
+                continue;
+            }
+            for (idx, (line, formatted_line)) in input.lines().zip(formatted.lines()).enumerate() {
+                if line != formatted_line {
+                    write_output(&format!("{}:{}: {}\n", file, idx + 1, line), exit_code);
+                    exit_code = EXIT_UNFORMATTED;
+                }
+            }
+        }
+        std::process::exit(exit_code);
+    }
+
+    let mut expressions = std::mem::take(&mut args.expressions);
+    for input in inputs {
+        expressions.extend(split_expressions(&input, args.paragraphs));
+    }
+    //colours only make sense when writing to terminal
+    let ansi = std::io::stdout().is_terminal();
+    let mut exit_code = 0;
+    for (idx, expr) in expressions.iter().enumerate() {
+        if let (true, Some(separator)) = (idx > 0, &args.separator) {
+            write_output(&format!("{}\n", separator), exit_code);
+        }
+        if args.debug_tree {
+            write_output(
+                &format!("{:#?}\n", asciimath_parser::parse(expr)),
+                exit_code,
+            );
+            continue;
+        }
+        let rendered = match args.format {
+            //trailing spaces are trimmed before styling, so no escape sequences are left
+            Format::Text if ansi && args.trim => {
+                asciimath::try_render_formula_with(expr, &args.options)
+                    .map(|formula| formula.trimmed_ansi_text())
+            }
+            Format::Text if ansi => asciimath::try_render_ansi_with(expr, &args.options),
+            Format::Text => asciimath::try_render_with(expr, &args.options),
+            Format::Html => {
+                asciimath::try_render_html_with(expr, &args.options, &HtmlOptions::default())
+            }
+            Format::Svg => {
+                asciimath::try_render_svg_with(expr, &args.options, &SvgOptions::default())
+            }
+            Format::Latex => latex::to_latex(expr),
+            Format::MathMl => mathml::to_mathml(expr),
+            Format::Typst => typst::to_typst(expr),
+        };
+        let rendered = rendered.unwrap_or_else(|e| {
+            eprintln!("amr: {}: {}", expr, e);
+            exit_code = EXIT_UNSUPPORTED;
+            expr.to_string()
+        });
+        write_output(&format!("{}\n", format_output(&rendered, &args)), exit_code);
+    }
+    std::process::exit(exit_code);
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = |a: &[&str]| parse_args(a.iter().map(|s| s.to_string()));
+        let parsed = args(&["--trim", "--indent", "4", "x^2", "--", "--y"]);
+        assert!(parsed.trim);
+        assert_eq!(parsed.indent, 4);
+        assert_eq!(parsed.expressions, vec!["x^2", "--y"]);
+        assert_eq!(parsed.format, Format::Text);
+        let parsed = args(&["--format", "latex", "1/2"]);
+        assert_eq!(parsed.format, Format::Latex);
+        let parsed = args(&["fmt", "--check", "--frac", "a.txt"]);
+        assert!(parsed.fmt && parsed.check);
+        assert_eq!(parsed.format_options.fraction_style, FractionStyle::Frac);
+        assert_eq!(parsed.files, vec!["a.txt"]);
+        let parsed = args(&["md", "--compact", "notes.md"]);
+        assert!(parsed.markdown && parsed.options.compact);
+        assert_eq!(parsed.files, vec!["notes.md"]);
+        assert!(parsed.expressions.is_empty());
+        let parsed = args(&["--format", "html", "--ascii", "--max-width", "20", "x"]);
+        assert_eq!(parsed.render_flags, vec!["--ascii", "--max-width"]);
+    }
+
+    #[test]
+    fn test_split_expressions() {
+        assert_eq!(split_expressions("a+b\n\n c \n", false), vec!["a+b", " c "]);
+        assert_eq!(split_expressions("a+\nb\n\n\nc", true), vec!["a+\nb", "c"]);
+    }
+
+    #[test]
+    fn test_format_lines() {
+        assert_eq!(
+            format_lines("  x = frac 1 2 \n\n{:y:}", &FormatOptions::default()),
+            "  x = 1/2\n\ny"
+        );
+    }
+
+    #[test]
+    fn test_format_output() {
+        let args = Args {
+            trim: true,
+            indent: 2,
+            ..Default::default()
+        };
+        assert_eq!(format_output(" 2 \nx  ", &args), "   2\n  x");
+    }
 }
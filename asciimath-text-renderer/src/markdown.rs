@@ -0,0 +1,285 @@
+//Rendering asciimath embedded in Markdown documents, for reading notes in terminal.
+//Display formulas are written in fenced blocks:
+//  ```asciimath
+//  sum_(n=1)^N n
+//  ```
+//and are replaced with their multi-line rendering (without the fences).
+//Inline formulas (`x^2` or $x^2$) are replaced with compact, single line rendering.
+//Everything else passes through unchanged, including other code blocks
+//(fenced or indented) and code spans with more than one backtick (``code``).
+//Formulas that cannot be rendered are left as they are.
+
+use crate::asciimath::{try_render_with, RenderError};
+use crate::render_options::RenderOptions;
+
+//opening fence: indentation, fence (``` or longer) and info string
+fn fence(line: &str) -> Option<(&str, &str, &str)> {
+    let rest = line.trim_start_matches(' ');
+    let indent = &line[..line.len() - rest.len()];
+    let ticks = rest.len() - rest.trim_start_matches('`').len();
+    if indent.len() > 3 || ticks < 3 {
+        return None;
+    }
+    Some((indent, &rest[..ticks], rest[ticks..].trim()))
+}
+
+//line of indented code block: four spaces or tab
+fn is_indented_code(line: &str) -> bool {
+    line.starts_with("    ") || line.starts_with('\t')
+}
+
+fn is_closing_fence(line: &str, opening: &str) -> bool {
+    matches!(fence(line), Some((_, ticks, "")) if ticks.len() >= opening.len())
+}
+
+//formulas left as they are, with the reason
+type Errors = Vec<(String, RenderError)>;
+
+//block is the whole fenced block, returned when formula cannot be rendered
+fn render_display(
+    formula: &str,
+    block: &str,
+    indent: &str,
+    options: &RenderOptions,
+    errors: &mut Errors,
+) -> String {
+    match try_render_with(formula, options) {
+        Ok(rendered) => rendered
+            .lines()
+            .map(|line| format!("{}{}\n", indent, line))
+            .collect(),
+        Err(e) => {
+            errors.push((formula.trim_end().to_string(), e));
+            block.to_string()
+        }
+    }
+}
+
+//$ opens formula when followed by non-space, and closes it when preceded by non-space
+//and not followed by digit, so that "$5 and $10" is not a formula
+fn closing_dollar(text: &str) -> Option<usize> {
+    if text.starts_with(|c: char| c.is_whitespace() || c == '$') {
+        return None;
+    }
+    let mut previous = ' ';
+    for (idx, c) in text.char_indices() {
+        if c == '$'
+            && idx > 0
+            && !previous.is_whitespace()
+            && previous != '\\'
+            && !text[idx + 1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            return Some(idx);
+        }
+        previous = c;
+    }
+    None
+}
+
+fn render_inline(
+    formula: &str,
+    delimited: &str,
+    options: &RenderOptions,
+    errors: &mut Errors,
+) -> String {
+    try_render_with(formula, options).unwrap_or_else(|e| {
+        errors.push((formula.to_string(), e));
+        delimited.to_string()
+    })
+}
+
+//inline formulas of a single line of text
+fn render_line(line: &str, options: &RenderOptions, errors: &mut Errors) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(idx) = rest.find(['`', '$', '\\']) {
+        result.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        if rest.starts_with('\\') {
+            //escaped character is written as it is
+            let len = rest[1..].chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+            result.push_str(&rest[..1 + len]);
+            rest = &rest[1 + len..];
+        } else if rest.starts_with('`') {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let closing = rest[ticks..]
+                .match_indices(&rest[..ticks])
+                .map(|(idx, _)| ticks + idx)
+                //closing run must have the same length
+                .find(|&idx| !rest[idx + ticks..].starts_with('`'));
+            match closing {
+                Some(end) if ticks == 1 => {
+                    result.push_str(&render_inline(
+                        &rest[1..end],
+                        &rest[..end + 1],
+                        options,
+                        errors,
+                    ));
+                    rest = &rest[end + 1..];
+                }
+                Some(end) => {
+                    result.push_str(&rest[..end + ticks]);
+                    rest = &rest[end + ticks..];
+                }
+                None => {
+                    result.push_str(&rest[..ticks]);
+                    rest = &rest[ticks..];
+                }
+            }
+        } else {
+            match closing_dollar(&rest[1..]) {
+                Some(end) => {
+                    result.push_str(&render_inline(
+                        &rest[1..end + 1],
+                        &rest[..end + 2],
+                        options,
+                        errors,
+                    ));
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    result.push('$');
+                    rest = &rest[1..];
+                }
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+pub fn render_markdown(document: &str) -> String {
+    render_markdown_with(document, &RenderOptions::default())
+}
+
+//options are used for display formulas, inline ones are additionally compact
+pub fn render_markdown_with(document: &str, options: &RenderOptions) -> String {
+    render_markdown_with_errors(document, options).0
+}
+
+//also returns formulas that were left as they are, with the reason
+pub fn render_markdown_with_errors(
+    document: &str,
+    options: &RenderOptions,
+) -> (String, Vec<(String, RenderError)>) {
+    let mut errors = vec![];
+    let inline_options = RenderOptions {
+        compact: true,
+        ..options.clone()
+    };
+    let mut result = String::new();
+    let mut lines = document.split_inclusive('\n');
+    //indented code block cannot interrupt a paragraph, so it starts only
+    //after blank line (or other block), and continues until non-indented line
+    let mut after_blank = true;
+    let mut in_indented_code = false;
+    while let Some(line) = lines.next() {
+        let text = line.trim_end_matches('\n');
+        let blank = text.trim().is_empty();
+        if !blank && is_indented_code(text) && (after_blank || in_indented_code) {
+            in_indented_code = true;
+            result.push_str(line);
+            continue;
+        }
+        in_indented_code &= blank;
+        after_blank = blank;
+        let Some((indent, ticks, info)) = fence(line) else {
+            result.push_str(&render_line(text, &inline_options, &mut errors));
+            result.push_str(&line[text.len()..]);
+            continue;
+        };
+        after_blank = true;
+        if info == "asciimath" {
+            let mut formula = String::new();
+            let mut block = line.to_string();
+            for line in lines.by_ref() {
+                block.push_str(line);
+                if is_closing_fence(line, ticks) {
+                    break;
+                }
+                formula.push_str(line);
+            }
+            result.push_str(&render_display(
+                &formula,
+                &block,
+                indent,
+                options,
+                &mut errors,
+            ));
+        } else {
+            //other code blocks are left as they are
+            result.push_str(line);
+            for line in lines.by_ref() {
+                result.push_str(line);
+                if is_closing_fence(line, ticks) {
+                    break;
+                }
+            }
+        }
+    }
+    (result, errors)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_inline_formulas() {
+        assert_eq!(render_markdown("area is `pi r^2`."), "area is πr².");
+        assert_eq!(render_markdown("half: $1/2$, or not"), "half: ½, or not");
+        assert_eq!(render_markdown("costs $5 and $10"), "costs $5 and $10");
+        assert_eq!(render_markdown("``raw `x` code``"), "``raw `x` code``");
+        assert_eq!(render_markdown(r"\`x\` and \$x$"), r"\`x\` and \$x$");
+        assert_eq!(render_markdown("unclosed `x"), "unclosed `x");
+        assert_eq!(render_markdown("`cancel x`"), "`cancel x`");
+    }
+
+    #[test]
+    fn test_display_formulas() {
+        assert_eq!(
+            render_markdown("Sum:\n```asciimath\n1/2\n```\n\n```rust\nlet x = `a`;\n```\n"),
+            concat!(
+                "Sum:\n",
+                " 1 \n",
+                "───\n",
+                " 2 \n",
+                "\n",
+                "```rust\n",
+                "let x = `a`;\n",
+                "```\n"
+            )
+        );
+        assert_eq!(
+            render_markdown("  ````asciimath\n  x^2\n  ````"),
+            "   2\n  x \n"
+        );
+        assert_eq!(
+            render_markdown("```asciimath\ncancel x\n```\n"),
+            "```asciimath\ncancel x\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let (rendered, errors) = render_markdown_with_errors(
+            "`x^2` and `cancel y`\n```asciimath\nabs z\n```\n",
+            &RenderOptions::default(),
+        );
+        assert_eq!(rendered, "x² and `cancel y`\n```asciimath\nabs z\n```\n");
+        let formulas: Vec<&str> = errors.iter().map(|(f, _)| f.as_str()).collect();
+        assert_eq!(formulas, vec!["cancel y", "abs z"]);
+        assert_eq!(errors[1].1.construct(), "abs");
+    }
+
+    #[test]
+    fn test_indented_code() {
+        assert_eq!(
+            render_markdown("    let s = `x^2`;\n\n    $y$\n`x^2`\n"),
+            "    let s = `x^2`;\n\n    $y$\nx²\n"
+        );
+        //indented line continuing a paragraph is not code
+        assert_eq!(render_markdown("text\n    `x^2`\n"), "text\n    x²\n");
+    }
+}
@@ -0,0 +1,403 @@
+//MathML export, for web pages and screen readers.
+//Walks the same asciimath_parser tree as visitors in asciimath module
+//and writes Presentation MathML:
+//  1/x^2  ->  <mfrac><mn>1</mn><msup><mi>x</mi><mn>2</mn></msup></mfrac>
+//Symbols are looked up in the same SYMBOLS table as used for rendering,
+//so both outputs show the same characters.
+
+use crate::asciimath::{
+    check_binary, check_unary, position, raw_argument, RenderError, BIG_OPERATORS, SYMBOLS,
+};
+use crate::font_style::FontStyle;
+use crate::html::escape;
+use crate::text_canvas::Color;
+
+const MATH_NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+
+fn element(tag: &str, content: &str) -> String {
+    format!("<{}>{}</{}>", tag, content, tag)
+}
+
+//empty arguments still need an element, ie. fraction has to have two children
+fn non_empty(mathml: String) -> String {
+    if mathml.is_empty() {
+        "<mrow></mrow>".to_string()
+    } else {
+        mathml
+    }
+}
+
+//brackets grow with their content
+fn fence(text: &str) -> String {
+    format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(text))
+}
+
+//character shown for the bracket, None for invisible ones ("{:" and ":}")
+fn bracket(bracket: &str) -> Option<&str> {
+    let bracket = match bracket {
+        "{:" | ":}" => return None,
+        "left(" => "(",
+        "right)" => ")",
+        "left[" => "[",
+        "right]" => "]",
+        "langle" => "(:",
+        "rangle" => ":)",
+        bracket => bracket,
+    };
+    Some(SYMBOLS.get(bracket).unwrap_or(&bracket))
+}
+
+fn fenced(left: &str, content: &str, right: &str) -> String {
+    let left = bracket(left).map(fence).unwrap_or_default();
+    let right = bracket(right).map(fence).unwrap_or_default();
+    element("mrow", &format!("{}{}{}", left, content, right))
+}
+
+//text under font command (ie. "bb x") is written with styled unicode characters,
+//as in rendered output (browsers implementing MathML Core ignore mathvariant)
+fn styled(text: &str, font: Option<FontStyle>) -> String {
+    match font {
+        Some(font) => escape(&font.apply(text)),
+        None => escape(text),
+    }
+}
+
+fn symbol(symbol: &str, font: Option<FontStyle>) -> String {
+    let text = SYMBOLS.get(symbol).unwrap_or(&symbol);
+    if text.trim().is_empty() {
+        //"quad", "\ "
+        element("mtext", &"\u{a0}".repeat(text.len()))
+    } else if text.chars().count() == 1 && text.chars().all(char::is_alphabetic) {
+        //"alpha", "RR"
+        element("mi", &styled(text, font))
+    } else {
+        element("mo", &styled(text, font))
+    }
+}
+
+//function name followed by invisible function application operator,
+//so that "sin x" is read as function of x, not multiplication
+fn with_arg(name: String, arg: String) -> String {
+    if arg.is_empty() {
+        name
+    } else {
+        element("mrow", &format!("{}<mo>&#x2061;</mo>{}", name, arg))
+    }
+}
+
+//source is the converted string, used to report error positions
+pub fn mathml_simple(
+    simple: &asciimath_parser::tree::Simple,
+    omit_braces: bool,
+    font: Option<FontStyle>,
+    source: &str,
+) -> Result<String, RenderError> {
+    let arg = |simple| mathml_simple(simple, true, font, source).map(non_empty);
+    match simple {
+        asciimath_parser::tree::Simple::Missing => Ok(String::new()),
+        asciimath_parser::tree::Simple::Number(number) => Ok(element("mn", &styled(number, font))),
+        asciimath_parser::tree::Simple::Text(text) => Ok(element("mtext", &styled(text, font))),
+        asciimath_parser::tree::Simple::Ident(ident) => {
+            if ident.chars().all(char::is_alphabetic) {
+                Ok(element("mi", &styled(ident, font)))
+            } else {
+                Ok(element("mo", &styled(ident, font)))
+            }
+        }
+        asciimath_parser::tree::Simple::Symbol(s) => Ok(symbol(s, font)),
+        asciimath_parser::tree::Simple::Unary(unary) => {
+            check_unary(unary.op, source)?;
+            let (tag, mark) = match unary.op {
+                "sqrt" => return Ok(element("msqrt", &arg(unary.arg())?)),
+                "hat" => ("mover", "^"),
+                "bar" | "overline" => ("mover", "¯"),
+                "vec" => ("mover", "→"),
+                "tilde" => ("mover", "~"),
+                "dot" => ("mover", "."),
+                "ddot" => ("mover", ".."),
+                "ul" | "underline" => ("munder", "_"),
+                op => match FontStyle::from_command(op) {
+                    Some(font) => {
+                        return mathml_simple(unary.arg(), true, Some(font), source).map(non_empty)
+                    }
+                    None => {
+                        return Err(RenderError::UnsupportedUnary {
+                            op: op.to_string(),
+                            position: position(source, op),
+                        })
+                    }
+                },
+            };
+            let accent = if tag == "mover" {
+                "accent"
+            } else {
+                "accentunder"
+            };
+            Ok(format!(
+                "<{} {}=\"true\">{}<mo>{}</mo></{}>",
+                tag,
+                accent,
+                arg(unary.arg())?,
+                mark,
+                tag
+            ))
+        }
+        asciimath_parser::tree::Simple::Func(func) => {
+            //function in simple context (ie. "sqrt sin x") can't have scripts
+            Ok(with_arg(
+                element("mi", &styled(func.func, font)),
+                mathml_simple(func.arg(), false, font, source)?,
+            ))
+        }
+        asciimath_parser::tree::Simple::Binary(binary) => {
+            check_binary(binary.op, source)?;
+            let first = arg(binary.first())?;
+            let second = arg(binary.second())?;
+            match binary.op {
+                "frac" => Ok(element("mfrac", &format!("{}{}", first, second))),
+                //mroot has radicand first, then index
+                "root" => Ok(element("mroot", &format!("{}{}", second, first))),
+                "stackrel" => Ok(element("mover", &format!("{}{}", second, first))),
+                "color" => {
+                    let color_name = raw_argument(binary.first(), source);
+                    if Color::from_name(color_name).is_none() {
+                        return Err(RenderError::UnknownColor {
+                            color: color_name.to_string(),
+                            position: position(source, color_name),
+                        });
+                    }
+                    Ok(format!(
+                        "<mstyle mathcolor=\"{}\">{}</mstyle>",
+                        escape(color_name.trim()),
+                        second
+                    ))
+                }
+                op => Err(RenderError::UnsupportedBinary {
+                    op: op.to_string(),
+                    position: position(source, op),
+                }),
+            }
+        }
+        asciimath_parser::tree::Simple::Group(group) => {
+            let expr = mathml_expr(&group.expr, font, source)?;
+            if omit_braces {
+                Ok(non_empty(expr))
+            } else {
+                Ok(fenced(group.left_bracket, &expr, group.right_bracket))
+            }
+        }
+        asciimath_parser::tree::Simple::Matrix(matrix) => {
+            let mut rows = String::new();
+            for row in matrix.rows() {
+                let mut cells = String::new();
+                for e in row {
+                    cells.push_str(&element("mtd", &mathml_expr(e, font, source)?));
+                }
+                rows.push_str(&element("mtr", &cells));
+            }
+            Ok(fenced(
+                matrix.left_bracket,
+                &element("mtable", &rows),
+                matrix.right_bracket,
+            ))
+        }
+    }
+}
+
+//limits: whether scripts go below and above base instead of to its side
+pub fn mathml_script(
+    base: String,
+    script: &asciimath_parser::tree::Script,
+    limits: bool,
+    font: Option<FontStyle>,
+    source: &str,
+) -> Result<String, RenderError> {
+    let script_arg = |simple| mathml_simple(simple, true, font, source).map(non_empty);
+    let (tag, scripts) = match (script.sub(), script.sup()) {
+        (None, None) => return Ok(base),
+        (Some(sub), None) => (if limits { "munder" } else { "msub" }, script_arg(sub)?),
+        (None, Some(sup)) => (if limits { "mover" } else { "msup" }, script_arg(sup)?),
+        (Some(sub), Some(sup)) => (
+            if limits { "munderover" } else { "msubsup" },
+            format!("{}{}", script_arg(sub)?, script_arg(sup)?),
+        ),
+    };
+    Ok(element(tag, &format!("{}{}", non_empty(base), scripts)))
+}
+
+//integrals keep their limits on the side, as they are usually typeset
+fn has_limits(symbol: &str) -> bool {
+    BIG_OPERATORS.contains(symbol) && !matches!(symbol, "int" | "oint")
+}
+
+pub fn mathml_script_func(
+    script_func: &asciimath_parser::tree::ScriptFunc,
+    omit_braces: bool,
+    font: Option<FontStyle>,
+    source: &str,
+) -> Result<String, RenderError> {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => {
+            let limits = matches!(
+                simple_script.simple,
+                asciimath_parser::tree::Simple::Symbol(symbol) if has_limits(symbol)
+            );
+            mathml_script(
+                mathml_simple(&simple_script.simple, omit_braces, font, source)?,
+                &simple_script.script,
+                limits,
+                font,
+                source,
+            )
+        }
+        asciimath_parser::tree::ScriptFunc::Func(func) => {
+            //scripts belong to function name, ie. "sin^2 x"
+            let name = mathml_script(
+                element("mi", &styled(func.func, font)),
+                &func.script,
+                has_limits(func.func),
+                font,
+                source,
+            )?;
+            Ok(with_arg(
+                name,
+                mathml_script_func(func.arg(), false, font, source)?,
+            ))
+        }
+    }
+}
+
+pub fn mathml_intermediate(
+    intermediate: &asciimath_parser::tree::Intermediate,
+    font: Option<FontStyle>,
+    source: &str,
+) -> Result<String, RenderError> {
+    match intermediate {
+        asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+            mathml_script_func(script_func, false, font, source)
+        }
+        asciimath_parser::tree::Intermediate::Frac(fraction) => Ok(element(
+            "mfrac",
+            &format!(
+                "{}{}",
+                non_empty(mathml_script_func(&fraction.numer, true, font, source)?),
+                non_empty(mathml_script_func(&fraction.denom, true, font, source)?)
+            ),
+        )),
+    }
+}
+
+//single element, wrapped in mrow when there are more
+pub fn mathml_expr(
+    expr: &asciimath_parser::tree::Expression,
+    font: Option<FontStyle>,
+    source: &str,
+) -> Result<String, RenderError> {
+    let items = expr
+        .iter()
+        .map(|e| mathml_intermediate(e, font, source))
+        .collect::<Result<Vec<String>, RenderError>>()?;
+    let items: Vec<String> = items.into_iter().filter(|item| !item.is_empty()).collect();
+    if items.len() == 1 {
+        Ok(items.concat())
+    } else {
+        Ok(element("mrow", &items.concat()))
+    }
+}
+
+pub fn to_mathml(expr: &str) -> Result<String, RenderError> {
+    Ok(format!(
+        "<math xmlns=\"{}\">{}</math>",
+        MATH_NAMESPACE,
+        mathml_expr(&asciimath_parser::parse(expr), None, expr)?
+    ))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::asciimath::try_render;
+
+    fn math(content: &str) -> String {
+        format!("<math xmlns=\"{}\">{}</math>", MATH_NAMESPACE, content)
+    }
+
+    #[test]
+    fn test_to_mathml() {
+        assert_eq!(
+            to_mathml("x^2+1").unwrap(),
+            math("<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo><mn>1</mn></mrow>")
+        );
+        assert_eq!(
+            to_mathml("(a+b)/c").unwrap(),
+            math("<mfrac><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow><mi>c</mi></mfrac>")
+        );
+        assert_eq!(
+            to_mathml("root 3 x").unwrap(),
+            math("<mroot><mi>x</mi><mn>3</mn></mroot>")
+        );
+        assert_eq!(
+            to_mathml("sqrt 2").unwrap(),
+            math("<msqrt><mn>2</mn></msqrt>")
+        );
+        assert_eq!(
+            to_mathml("sum_(n=1)^N n").unwrap(),
+            math(concat!(
+                "<mrow><munderover><mo>∑</mo><mrow><mi>n</mi><mo>=</mo><mn>1</mn></mrow>",
+                "<mi>N</mi></munderover><mi>n</mi></mrow>"
+            ))
+        );
+        assert_eq!(
+            to_mathml("int_0^1").unwrap(),
+            math("<msubsup><mo>∫</mo><mn>0</mn><mn>1</mn></msubsup>")
+        );
+        assert_eq!(
+            to_mathml("(:x:)").unwrap(),
+            math(concat!(
+                "<mrow><mo fence=\"true\" stretchy=\"true\">〈</mo><mi>x</mi>",
+                "<mo fence=\"true\" stretchy=\"true\">〉</mo></mrow>"
+            ))
+        );
+        assert_eq!(
+            to_mathml("[[a,b],[c,d]]").unwrap(),
+            math(concat!(
+                "<mrow><mo fence=\"true\" stretchy=\"true\">[</mo><mtable>",
+                "<mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>",
+                "<mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>",
+                "</mtable><mo fence=\"true\" stretchy=\"true\">]</mo></mrow>"
+            ))
+        );
+        assert_eq!(
+            to_mathml("sin x").unwrap(),
+            math("<mrow><mi>sin</mi><mo>&#x2061;</mo><mi>x</mi></mrow>")
+        );
+        assert_eq!(
+            to_mathml("\"a<b\" \"c\"").unwrap(),
+            math("<mrow><mtext>a&lt;b</mtext><mtext>c</mtext></mrow>")
+        );
+        assert_eq!(
+            to_mathml("bbb R alpha").unwrap(),
+            math(concat!("<mrow><mi>ℝ</mi>", "<mi>α</mi></mrow>"))
+        );
+        assert_eq!(
+            to_mathml("hat x").unwrap(),
+            math("<mover accent=\"true\"><mi>x</mi><mo>^</mo></mover>")
+        );
+        assert_eq!(
+            to_mathml("1/").unwrap(),
+            math("<mfrac><mn>1</mn><mrow></mrow></mfrac>")
+        );
+        assert_eq!(
+            to_mathml("color(red)(x)").unwrap(),
+            math("<mstyle mathcolor=\"red\"><mi>x</mi></mstyle>")
+        );
+        //constructs text renderer can't draw are rejected the same way
+        assert_eq!(
+            to_mathml("color(nope)(x)"),
+            Err(try_render("color(nope)(x)").unwrap_err())
+        );
+        assert_eq!(to_mathml("abs(x)"), Err(try_render("abs(x)").unwrap_err()));
+        assert!(to_mathml("cancel x").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//Choices renderer makes that are matter of taste or terminal capabilities.
+//Default options give the same output as plain render(expr).
+
+use crate::font_style::FontFallback;
+use crate::glyph_set::GlyphSet;
+use crate::text_canvas::AmbiguousWidth;
+
+//how scripts of big operators (sum, lim, ...) are placed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScriptStyle {
+    //limits centered above and below operator
+    #[default]
+    Display,
+    //limits as sub- and superscript, on the right side of operator
+    Inline,
+}
+
+//how brackets taller than one line are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BracketStyle {
+    //assembled from bracket pieces: ⎛ ⎜ ⎝
+    #[default]
+    Extended,
+    //one line bracket repeated on every line: ( ( (
+    //for fonts missing bracket pieces
+    Simple,
+}
+
+//what "*" is rendered as
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MultiplicationSign {
+    #[default]
+    Dot, //"⋅"
+    Cross,    //"×"
+    Asterisk, //"*"
+}
+
+impl MultiplicationSign {
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            MultiplicationSign::Dot => "⋅",
+            MultiplicationSign::Cross => "×",
+            MultiplicationSign::Asterisk => "*",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RenderOptions {
+    pub script_style: ScriptStyle,
+    //surround binary operators and relations with spaces: "a + b = c"
+    pub operator_spacing: bool,
+    pub multiplication_sign: MultiplicationSign,
+    //how many cells fraction bar sticks out on each side of numerator/denominator
+    pub fraction_overhang: usize,
+    pub bracket_style: BracketStyle,
+    //width of East Asian Ambiguous characters in formula text,
+    //drawn glyphs are always one cell, see AmbiguousWidth
+    pub ambiguous_width: AmbiguousWidth,
+    //letters and digits a font command has no styled form for, ie. "cc alpha"
+    pub font_fallback: FontFallback,
+    //glyphs to draw brackets, lines etc. with, see GlyphSet presets
+    pub glyphs: GlyphSet,
+    //whole formula in one line: x² + ½, see compact module
+    pub compact: bool,
+    //break lines wider than that (in terminal columns), see line_break module
+    pub max_width: Option<usize>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            script_style: ScriptStyle::default(),
+            operator_spacing: false,
+            multiplication_sign: MultiplicationSign::default(),
+            fraction_overhang: 1,
+            bracket_style: BracketStyle::default(),
+            ambiguous_width: AmbiguousWidth::default(),
+            font_fallback: FontFallback::default(),
+            glyphs: GlyphSet::default(),
+            compact: false,
+            max_width: None,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//Rendered formula along with information where its parts came from,
+//for editors that show preview next to the source and need to map
+//between the two (clicking preview moves the cursor and the reverse).
+//Every cell maps to the input bytes of the innermost node drawn there,
+//cells not covered by any node (padding) map to nothing.
+//Baseline is the row that lines up with surrounding text
+//(fraction bar, main row of the formula), so formulas can be embedded in prose:
+//         1
+//  where ─── holds
+//         2
+
+use std::ops::Range;
+
+use crate::glyph_set::GlyphSet;
+use crate::renderer::{Drawable, Literal};
+use crate::text_canvas::{NodeBox, TextCanvas};
+
+#[derive(Clone, Debug)]
+pub struct RenderedFormula {
+    canvas: TextCanvas,
+    text: String,
+    baseline: usize,
+}
+
+impl RenderedFormula {
+    pub fn new(canvas: TextCanvas, baseline: usize) -> Self {
+        RenderedFormula {
+            text: canvas.as_text(),
+            canvas,
+            baseline,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    //with colours as ANSI escape sequences
+    pub fn ansi_text(&self) -> String {
+        self.canvas.as_ansi_text()
+    }
+
+    //ansi_text without trailing spaces in lines
+    pub fn trimmed_ansi_text(&self) -> String {
+        self.canvas.as_trimmed_ansi_text()
+    }
+
+    //rows of text, all of them padded to the same width
+    pub fn lines(&self) -> Vec<&str> {
+        self.text.lines().collect()
+    }
+
+    //in cells (terminal columns)
+    pub fn width(&self) -> usize {
+        self.canvas.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.canvas.height
+    }
+
+    //row (counting from the top) that lines up with surrounding text
+    pub fn baseline(&self) -> usize {
+        self.baseline
+    }
+
+    //input bytes that produced given cell
+    pub fn span_at(&self, x: usize, y: usize) -> Option<Range<usize>> {
+        if x < self.width() && y < self.height() {
+            self.canvas.node_box_at(x, y).map(|b| b.span.clone())
+        } else {
+            None
+        }
+    }
+
+    //boxes of all nodes, parents before their children
+    pub fn nodes(&self) -> &[NodeBox] {
+        self.canvas.node_boxes()
+    }
+
+    //cells (x, y) produced by the innermost node containing given input byte,
+    //so that cursor position in the source can be shown in preview
+    pub fn cells_at(&self, offset: usize) -> Vec<(usize, usize)> {
+        let cells = || (0..self.height()).flat_map(|y| (0..self.width()).map(move |x| (x, y)));
+        let innermost = cells()
+            .filter_map(|(x, y)| self.span_at(x, y))
+            .filter(|span| span.contains(&offset))
+            .min_by_key(|span| span.len());
+        let Some(innermost) = innermost else {
+            return vec![];
+        };
+        cells()
+            .filter(|&(x, y)| self.span_at(x, y).as_ref() == Some(&innermost))
+            .collect()
+    }
+}
+
+//part of a line of text with formulas in it
+#[derive(Clone, Copy, Debug)]
+pub enum InlineRun<'a> {
+    //single line of text
+    Text(&'a str),
+    Formula(&'a RenderedFormula),
+}
+
+//lay out runs next to each other, with text and baselines of formulas in one row.
+//Node boxes of formulas are kept (moved to their new place),
+//their spans still refer to inputs of respective formulas.
+pub fn layout_inline(runs: &[InlineRun]) -> RenderedFormula {
+    //(canvas, baseline) of every run
+    let parts: Vec<(TextCanvas, usize)> = runs
+        .iter()
+        .map(|run| match run {
+            InlineRun::Text(text) => (Literal::new(text).to_canvas(&GlyphSet::default()), 0),
+            InlineRun::Formula(formula) => (formula.canvas.clone(), formula.baseline),
+        })
+        .collect();
+    let baseline = parts.iter().map(|(_, b)| *b).max().unwrap_or(0);
+    let height = parts
+        .iter()
+        .map(|(canvas, b)| baseline - b + canvas.height)
+        .max()
+        .unwrap_or(0);
+    let mut canvas = TextCanvas::new(parts.iter().map(|(c, _)| c.width).sum(), height);
+    let mut x = 0;
+    for (part, b) in &parts {
+        canvas.draw(part, x, baseline - b);
+        x += part.width;
+    }
+    RenderedFormula::new(canvas, baseline)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_rendered_formula() {
+        let mut tc = TextCanvas::new(2, 1);
+        tc.set(0, 0, "a");
+        tc.add_node_box(NodeBox {
+            span: 3..4,
+            kind: "identifier",
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        });
+        let formula = RenderedFormula::new(tc, 0);
+        assert_eq!(formula.text(), "a ");
+        assert_eq!(formula.span_at(0, 0), Some(3..4));
+        assert_eq!(formula.span_at(1, 0), None);
+        assert_eq!(formula.span_at(5, 5), None);
+        assert_eq!(formula.cells_at(3), vec![(0, 0)]);
+        assert_eq!(formula.cells_at(4), vec![]);
+    }
+
+    #[test]
+    fn test_layout_inline() {
+        let mut tc = TextCanvas::new(1, 3);
+        tc.set(0, 0, "1");
+        tc.set(0, 1, "─");
+        tc.set(0, 2, "2");
+        let fraction = RenderedFormula::new(tc, 1);
+        let line = layout_inline(&[
+            InlineRun::Text("x = "),
+            InlineRun::Formula(&fraction),
+            InlineRun::Text(" holds"),
+        ]);
+        assert_eq!(
+            line.lines(),
+            vec!["    1      ", "x = ─ holds", "    2      "]
+        );
+        assert_eq!(line.baseline(), 1);
+        assert_eq!(layout_inline(&[]).lines(), Vec::<&str>::new());
+    }
+}
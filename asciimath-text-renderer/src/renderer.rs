@@ -1,7 +1,10 @@
 use std::fmt::Debug;
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::text_canvas::TextCanvas;
+use crate::glyph_set::GlyphSet;
+use crate::render_options::{BracketStyle, RenderOptions};
+use crate::text_canvas::{grapheme_width, AmbiguousWidth, CellStyle, Color, NodeBox, TextCanvas};
 
 //TODO: level for sqrt/root should match radicand level,
 // for example: 1 + sqrt (-1^4/2)   "1" is rendered to high
@@ -20,9 +23,11 @@ pub enum BracketType {
     Vertical,    //"￨"
 }
 
-pub trait Drawable: Debug {
-    fn width(&self) -> usize;
-    fn height(&self) -> usize;
+//size of a rendered expression
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayoutBox {
+    pub width: usize,
+    pub height: usize,
     /*where the horizontal middle of expression is
      so that we know where to draw it
      provided in relation to top of the expression
@@ -30,47 +35,88 @@ pub trait Drawable: Debug {
      - + \/2   <- level
      2
     */
-    fn level(&self) -> usize;
-    fn as_text(&self) -> String;
-    fn to_canvas(&self) -> TextCanvas;
+    pub level: usize,
+}
+
+//Rendering is done in two passes:
+//layout - every node computes its box once, when it's created
+//  (children are always created first, so their boxes are known),
+//  and keeps it, so asking for size never walks the subtree
+//draw - root allocates single canvas of its size and every node
+//  draws itself directly into it, at the offset given by parent
+pub trait Drawable: Debug {
+    fn layout(&self) -> LayoutBox;
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize);
+
+    fn width(&self) -> usize {
+        self.layout().width
+    }
+    fn height(&self) -> usize {
+        self.layout().height
+    }
+    fn level(&self) -> usize {
+        self.layout().level
+    }
+    fn to_canvas(&self, glyphs: &GlyphSet) -> TextCanvas {
+        let mut tc = TextCanvas::new(self.width(), self.height());
+        self.draw(&mut tc, glyphs, 0, 0);
+        tc
+    }
+    fn as_text(&self) -> String {
+        self.to_canvas(&GlyphSet::default()).as_text()
+    }
 }
 
 #[derive(Clone, Debug)]
 //hold vec of graphemes, which will be simply displayed as literals
 //in other words, it's a line of text
+//Every grapheme keeps its display width (1 or 2 columns).
 pub struct Literal {
-    value: Vec<String>,
+    value: Vec<(String, usize)>,
 }
 
 impl Literal {
     pub fn new(from_str: &str) -> Self {
-        Literal {
-            value: from_str.graphemes(true).map(|s| s.to_string()).collect(),
+        Literal::with_ambiguous_width(from_str, AmbiguousWidth::default())
+    }
+
+    pub fn with_ambiguous_width(from_str: &str, ambiguous_width: AmbiguousWidth) -> Self {
+        let mut value: Vec<(String, usize)> = vec![];
+        for grapheme in from_str.graphemes(true) {
+            match (grapheme_width(grapheme, ambiguous_width), value.last_mut()) {
+                //zero-width grapheme goes into preceding cell
+                (0, Some((previous, _))) => previous.push_str(grapheme),
+                (width, _) => value.push((grapheme.to_string(), std::cmp::max(width, 1))),
+            }
         }
+        Literal { value }
     }
 }
 
 impl Drawable for Literal {
-    fn width(&self) -> usize {
-        self.value.len()
-    }
-    fn height(&self) -> usize {
-        1
+    fn layout(&self) -> LayoutBox {
+        LayoutBox {
+            width: self.value.iter().map(|(_, width)| width).sum(),
+            height: 1,
+            level: 0,
+        }
     }
 
     fn as_text(&self) -> String {
-        self.value.join("")
-    }
-    fn to_canvas(&self) -> TextCanvas {
-        let mut tc = TextCanvas::new(self.value.len(), 1);
-        for (idx, s) in self.value.iter().enumerate() {
-            tc.set(idx, 0, s);
-        }
-        tc
+        self.value.iter().map(|(s, _)| s.as_str()).collect()
     }
 
-    fn level(&self) -> usize {
-        0
+    //text is not a glyph, it's drawn as it is
+    fn draw(&self, text_canvas: &mut TextCanvas, _glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let mut x = at_x;
+        for (s, width) in self.value.iter() {
+            if *width == 2 {
+                text_canvas.set_wide(x, at_y, s);
+            } else {
+                text_canvas.set(x, at_y, s);
+            }
+            x += width;
+        }
     }
 }
 
@@ -78,48 +124,50 @@ impl Drawable for Literal {
 pub struct Div {
     expr1: Box<dyn Drawable>,
     expr2: Box<dyn Drawable>,
+    layout: LayoutBox,
 }
 
 impl Div {
-    pub fn new(expr1: Box<dyn Drawable>, expr2: Box<dyn Drawable>) -> Self {
-        Div { expr1, expr2 }
+    pub fn new(
+        expr1: Box<dyn Drawable>,
+        expr2: Box<dyn Drawable>,
+        options: &RenderOptions,
+    ) -> Self {
+        let layout = LayoutBox {
+            width: std::cmp::max(expr1.width(), expr2.width()) + 2 * options.fraction_overhang,
+            height: expr1.height() + expr2.height() + 1,
+            level: expr1.height(),
+        };
+        Div {
+            expr1,
+            expr2,
+            layout,
+        }
     }
 }
 
 impl Drawable for Div {
-    fn width(&self) -> usize {
-        std::cmp::max(self.expr1.width(), self.expr2.width()) + 2
-    }
-
-    fn height(&self) -> usize {
-        self.expr1.height() + self.expr2.height() + 1
-    }
-
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-
-        let expr1_tc = self.expr1.to_canvas();
-        let expr2_tc = self.expr2.to_canvas();
-
-        result.draw(&expr1_tc, (self.width() - self.expr1.width() + 1) / 2, 0);
-        result.draw(
-            &expr2_tc,
-            (self.width() - self.expr2.width() + 1) / 2,
-            self.expr1.height() + 1,
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let width = self.layout.width;
+        self.expr1.draw(
+            text_canvas,
+            glyphs,
+            at_x + (width - self.expr1.width()).div_ceil(2),
+            at_y,
+        );
+        self.expr2.draw(
+            text_canvas,
+            glyphs,
+            at_x + (width - self.expr2.width()).div_ceil(2),
+            at_y + self.expr1.height() + 1,
         );
-        for idx in 0..self.width() {
-            result.set(idx, self.expr1.height(), "─")
+        for idx in 0..width {
+            text_canvas.set(at_x + idx, at_y + self.expr1.height(), glyphs.get("─"))
         }
-
-        result
-    }
-
-    fn level(&self) -> usize {
-        self.expr1.height()
     }
 }
 
@@ -130,49 +178,52 @@ impl Drawable for Div {
 pub struct Stack {
     expr1: Box<dyn Drawable>,
     expr2: Box<dyn Drawable>,
+    layout: LayoutBox,
 }
 
 impl Stack {
     pub fn new(expr1: Box<dyn Drawable>, expr2: Box<dyn Drawable>) -> Self {
-        Stack { expr1, expr2 }
+        let layout = LayoutBox {
+            width: std::cmp::max(expr1.width(), expr2.width()),
+            height: expr1.height() + expr2.height(),
+            level: expr1.height().saturating_sub(1),
+        };
+        Stack {
+            expr1,
+            expr2,
+            layout,
+        }
     }
 }
 
 impl Drawable for Stack {
-    fn width(&self) -> usize {
-        std::cmp::max(self.expr1.width(), self.expr2.width())
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn height(&self) -> usize {
-        self.expr1.height() + self.expr2.height()
-    }
-
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-
-        let expr1_tc = self.expr1.to_canvas();
-        let expr2_tc = self.expr2.to_canvas();
-
-        result.draw(&expr1_tc, (self.width() - self.expr1.width() + 1) / 2, 0);
-        result.draw(
-            &expr2_tc,
-            (self.width() - self.expr2.width() + 1) / 2,
-            self.expr1.height(),
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let width = self.layout.width;
+        self.expr1.draw(
+            text_canvas,
+            glyphs,
+            at_x + (width - self.expr1.width()).div_ceil(2),
+            at_y,
+        );
+        self.expr2.draw(
+            text_canvas,
+            glyphs,
+            at_x + (width - self.expr2.width()).div_ceil(2),
+            at_y + self.expr1.height(),
         );
-        result
-    }
-
-    fn level(&self) -> usize {
-        self.expr1.height() - 1
     }
 }
 
-pub fn bracket_width(bracket_type: &BracketType, expr_height: usize) -> usize {
-    if expr_height == 0 {
+pub fn bracket_width(
+    bracket_type: &BracketType,
+    bracket_style: BracketStyle,
+    expr_height: usize,
+) -> usize {
+    if expr_height == 0 || bracket_style == BracketStyle::Simple {
         if *bracket_type == BracketType::None {
             return 0;
         } else {
@@ -184,7 +235,7 @@ pub fn bracket_width(bracket_type: &BracketType, expr_height: usize) -> usize {
         BracketType::LeftRound | BracketType::RightRound => 1,
         BracketType::LeftSquare | BracketType::RightSquare => 1,
         BracketType::LeftCurly | BracketType::RightCurly => 1,
-        BracketType::LeftAngled | BracketType::RightAngled => (expr_height + 1) / 2,
+        BracketType::LeftAngled | BracketType::RightAngled => expr_height.div_ceil(2),
         BracketType::Vertical => 1,
     }
 }
@@ -222,6 +273,7 @@ pub fn draw_long_bracket(
     text_canvas.set(at_x, y, bottom);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn draw_long_curly_bracket(
     text_canvas: &mut TextCanvas,
     expr_height: usize,
@@ -237,7 +289,7 @@ pub fn draw_long_curly_bracket(
     text_canvas.set(at_x, at_y, top);
     y += 1;
     for _ in 0..expr_height - 2 {
-        if y == expr_height / 2 {
+        if y - at_y == expr_height / 2 {
             text_canvas.set(at_x, y, middle);
         } else {
             text_canvas.set(at_x, y, extension);
@@ -272,7 +324,7 @@ pub fn draw_long_angled_bracket_left(
 ) {
     //expr_height >= 2
     let mut y = at_y;
-    if expr_height % 2 == 0 {
+    if expr_height.is_multiple_of(2) {
         for idx in (0..(expr_height / 2)).rev() {
             text_canvas.set(at_x + idx, y, upper);
             y += 1;
@@ -307,7 +359,7 @@ pub fn draw_long_angled_bracket_right(
 ) {
     //expr_height >= 2
     let mut y = at_y;
-    if expr_height % 2 == 0 {
+    if expr_height.is_multiple_of(2) {
         for idx in 0..(expr_height / 2) {
             text_canvas.set(at_x + idx, y, upper);
             y += 1;
@@ -322,7 +374,7 @@ pub fn draw_long_angled_bracket_right(
             y += 1;
         }
 
-        text_canvas.set(at_x + y - 1, y, middle);
+        text_canvas.set(at_x + (y - at_y) - 1, y, middle);
         y += 1;
         for idx in (0..((expr_height) / 2)).rev() {
             text_canvas.set(at_x + idx, y, lower);
@@ -331,66 +383,166 @@ pub fn draw_long_angled_bracket_right(
     }
 }
 
+//one line form of a bracket
+fn bracket_glyph(bracket_type: &BracketType) -> &'static str {
+    match bracket_type {
+        BracketType::None => "",
+        BracketType::LeftRound => "(",
+        BracketType::RightRound => ")",
+        BracketType::LeftSquare => "[",
+        BracketType::RightSquare => "]",
+        BracketType::LeftCurly => "{",
+        BracketType::RightCurly => "}",
+        BracketType::LeftAngled => "⟨",
+        BracketType::RightAngled => "⟩",
+        BracketType::Vertical => "￨",
+    }
+}
+
 pub fn draw_bracket(
     text_canvas: &mut TextCanvas,
+    glyphs: &GlyphSet,
     bracket_type: &BracketType,
+    bracket_style: BracketStyle,
     expr_height: usize,
     at_x: usize,
     at_y: usize,
 ) {
+    if bracket_style == BracketStyle::Simple {
+        if *bracket_type != BracketType::None {
+            draw_simple_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get(bracket_glyph(bracket_type)),
+            );
+        }
+        return;
+    }
     match bracket_type {
         BracketType::None => {}
-        BracketType::LeftRound if expr_height <= 1 => text_canvas.set(at_x, at_y, "("),
+        BracketType::LeftRound if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get("(")),
         BracketType::LeftRound => {
-            draw_long_bracket(text_canvas, expr_height, at_x, at_y, "⎛", "⎜", "⎝");
+            draw_long_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎛"),
+                glyphs.get("⎜"),
+                glyphs.get("⎝"),
+            );
         }
-        BracketType::RightRound if expr_height <= 1 => text_canvas.set(at_x, at_y, ")"),
+        BracketType::RightRound if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get(")")),
         BracketType::RightRound => {
-            draw_long_bracket(text_canvas, expr_height, at_x, at_y, "⎞", "⎜", "⎠");
+            draw_long_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎞"),
+                glyphs.get("⎜"),
+                glyphs.get("⎠"),
+            );
         }
-        BracketType::LeftSquare if expr_height <= 1 => text_canvas.set(at_x, at_y, "["),
+        BracketType::LeftSquare if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get("[")),
         BracketType::LeftSquare => {
-            draw_long_bracket(text_canvas, expr_height, at_x, at_y, "⎡", "⎥", "⎣");
+            draw_long_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎡"),
+                glyphs.get("⎥"),
+                glyphs.get("⎣"),
+            );
+        }
+        BracketType::RightSquare if expr_height <= 1 => {
+            text_canvas.set(at_x, at_y, glyphs.get("]"))
         }
-        BracketType::RightSquare if expr_height <= 1 => text_canvas.set(at_x, at_y, "]"),
         BracketType::RightSquare => {
-            draw_long_bracket(text_canvas, expr_height, at_x, at_y, "⎤", "⎥", "⎦");
+            draw_long_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎤"),
+                glyphs.get("⎥"),
+                glyphs.get("⎦"),
+            );
         }
-        BracketType::LeftCurly if expr_height <= 1 => text_canvas.set(at_x, at_y, "{"),
+        BracketType::LeftCurly if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get("{")),
         BracketType::LeftCurly if expr_height == 2 => {
-            text_canvas.set(at_x, at_y, "⎰");
-            text_canvas.set(at_x, at_y + 1, "⎱");
+            text_canvas.set(at_x, at_y, glyphs.get("⎰"));
+            text_canvas.set(at_x, at_y + 1, glyphs.get("⎱"));
         }
         BracketType::LeftCurly => {
-            draw_long_curly_bracket(text_canvas, expr_height, at_x, at_y, "⎧", "⎪", "⎨", "⎩");
+            draw_long_curly_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎧"),
+                glyphs.get("⎪"),
+                glyphs.get("⎨"),
+                glyphs.get("⎩"),
+            );
         }
-        BracketType::RightCurly if expr_height <= 1 => text_canvas.set(at_x, at_y, "}"),
+        BracketType::RightCurly if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get("}")),
         BracketType::RightCurly if expr_height == 2 => {
-            text_canvas.set(at_x, at_y, "⎱");
-            text_canvas.set(at_x, at_y + 1, "⎰");
+            text_canvas.set(at_x, at_y, glyphs.get("⎱"));
+            text_canvas.set(at_x, at_y + 1, glyphs.get("⎰"));
         }
         BracketType::RightCurly => {
-            draw_long_curly_bracket(text_canvas, expr_height, at_x, at_y, "⎫", "⎪", "⎬", "⎭");
+            draw_long_curly_bracket(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("⎫"),
+                glyphs.get("⎪"),
+                glyphs.get("⎬"),
+                glyphs.get("⎭"),
+            );
         }
-        BracketType::LeftAngled if expr_height <= 1 => text_canvas.set(at_x, at_y, "⟨"),
+        BracketType::LeftAngled if expr_height <= 1 => text_canvas.set(at_x, at_y, glyphs.get("⟨")),
 
         BracketType::LeftAngled if expr_height == 2 => {
-            text_canvas.set(at_x, at_y, "╱");
-            text_canvas.set(at_x, at_y + 1, "╲");
+            text_canvas.set(at_x, at_y, glyphs.get("╱"));
+            text_canvas.set(at_x, at_y + 1, glyphs.get("╲"));
         }
         BracketType::LeftAngled => {
-            draw_long_angled_bracket_left(text_canvas, expr_height, at_x, at_y, "╱", "🮤", "╲");
+            draw_long_angled_bracket_left(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("╱"),
+                glyphs.get("🮤"),
+                glyphs.get("╲"),
+            );
+        }
+        BracketType::RightAngled if expr_height <= 1 => {
+            text_canvas.set(at_x, at_y, glyphs.get("⟩"))
         }
-        BracketType::RightAngled if expr_height <= 1 => text_canvas.set(at_x, at_y, "⟩"),
         BracketType::RightAngled if expr_height == 2 => {
-            text_canvas.set(at_x, at_y, "╲");
-            text_canvas.set(at_x, at_y + 1, "╱");
+            text_canvas.set(at_x, at_y, glyphs.get("╲"));
+            text_canvas.set(at_x, at_y + 1, glyphs.get("╱"));
         }
         BracketType::RightAngled => {
-            draw_long_angled_bracket_right(text_canvas, expr_height, at_x, at_y, "╲", "🮥", "╱");
+            draw_long_angled_bracket_right(
+                text_canvas,
+                expr_height,
+                at_x,
+                at_y,
+                glyphs.get("╲"),
+                glyphs.get("🮥"),
+                glyphs.get("╱"),
+            );
         }
         BracketType::Vertical => {
-            draw_simple_bracket(text_canvas, expr_height, at_x, at_y, "￨");
+            draw_simple_bracket(text_canvas, expr_height, at_x, at_y, glyphs.get("￨"));
         }
     }
 }
@@ -402,6 +554,8 @@ pub struct Group {
     left_bracket: BracketType,
     expr: Option<Box<dyn Drawable>>,
     right_bracket: BracketType,
+    bracket_style: BracketStyle,
+    layout: LayoutBox,
 }
 
 impl Group {
@@ -409,113 +563,71 @@ impl Group {
         left_bracket: BracketType,
         expr: Option<Box<dyn Drawable>>,
         right_bracket: BracketType,
+        options: &RenderOptions,
     ) -> Self {
+        let bracket_style = options.bracket_style;
+        let expr_layout = if let Some(expr) = &expr {
+            expr.layout()
+        } else {
+            LayoutBox::default()
+        };
+        let layout = LayoutBox {
+            width: bracket_width(&left_bracket, bracket_style, expr_layout.height)
+                + expr_layout.width
+                + bracket_width(&right_bracket, bracket_style, expr_layout.height),
+            //empty group, ie. "()", still needs a line for brackets
+            height: std::cmp::max(expr_layout.height, 1),
+            //expr.level()
+            level: expr_layout.height / 2,
+        };
         Group {
             left_bracket,
             expr,
             right_bracket,
+            bracket_style,
+            layout,
         }
     }
 }
 
 impl Drawable for Group {
-    fn width(&self) -> usize {
-        bracket_width(
-            &self.left_bracket,
-            if let Some(expr) = &self.expr {
-                expr.height()
-            } else {
-                0
-            },
-        ) + if let Some(expr) = &self.expr {
-            expr.width()
-        } else {
-            0
-        } + bracket_width(
-            &self.right_bracket,
-            if let Some(expr) = &self.expr {
-                expr.height()
-            } else {
-                0
-            },
-        )
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn height(&self) -> usize {
-        if let Some(expr) = &self.expr {
-            expr.height()
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let (expr_width, expr_height) = if let Some(expr) = &self.expr {
+            (expr.width(), expr.height())
         } else {
-            1
-        }
-    }
-
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-        let lbw = bracket_width(
-            &self.left_bracket,
-            if let Some(expr) = &self.expr {
-                expr.height()
-            } else {
-                0
-            },
-        );
-        let rbw = bracket_width(
-            &self.right_bracket,
-            if let Some(expr) = &self.expr {
-                expr.height()
-            } else {
-                0
-            },
-        );
-        let expr_width = if let Some(expr) = &self.expr {
-            expr.width()
-        } else {
-            0
+            (0, 0)
         };
+        let lbw = bracket_width(&self.left_bracket, self.bracket_style, expr_height);
+        let rbw = bracket_width(&self.right_bracket, self.bracket_style, expr_height);
         if lbw > 0 {
             draw_bracket(
-                &mut result,
+                text_canvas,
+                glyphs,
                 &self.left_bracket,
-                if let Some(expr) = &self.expr {
-                    expr.height()
-                } else {
-                    0
-                },
-                0,
-                0,
+                self.bracket_style,
+                expr_height,
+                at_x,
+                at_y,
             );
         }
         if let Some(expr) = &self.expr {
-            let expr_tc = expr.to_canvas();
-            result.draw(&expr_tc, lbw, 0);
+            expr.draw(text_canvas, glyphs, at_x + lbw, at_y);
         }
         if rbw > 0 {
             draw_bracket(
-                &mut result,
+                text_canvas,
+                glyphs,
                 &self.right_bracket,
-                if let Some(expr) = &self.expr {
-                    expr.height()
-                } else {
-                    0
-                },
-                lbw + expr_width,
-                0,
+                self.bracket_style,
+                expr_height,
+                at_x + lbw + expr_width,
+                at_y,
             );
         }
-        result
-    }
-
-    fn level(&self) -> usize {
-        if let Some(expr) = &self.expr {
-            //expr.level()
-            expr.height() / 2
-        } else {
-            0
-        }
     }
 }
 
@@ -529,7 +641,15 @@ pub struct Matrix {
     left_bracket: BracketType,
     exprs: Vec<Box<dyn Drawable>>,
     right_bracket: BracketType,
+    bracket_style: BracketStyle,
     num_colls: usize,
+    //max width per column and max height per row
+    //for example:
+    //[a*2, c] gives column widths [3, 1]
+    //[b/2, d]       row heights [1, 3]
+    column_widths: Vec<usize>,
+    row_heights: Vec<usize>,
+    layout: LayoutBox,
 }
 
 impl Matrix {
@@ -538,101 +658,219 @@ impl Matrix {
         exprs: Vec<Box<dyn Drawable>>,
         right_bracket: BracketType,
         num_colls: usize,
+        options: &RenderOptions,
     ) -> Self {
+        let num_rows = exprs.len() / num_colls;
+        let row_heights: Vec<usize> = exprs
+            .chunks(num_colls)
+            .map(|row| row.iter().map(|e| e.height()).max().unwrap_or(0))
+            .collect();
+        let column_widths: Vec<usize> = (0..num_colls)
+            .map(|coll_idx| {
+                exprs
+                    .iter()
+                    .skip(coll_idx)
+                    .step_by(num_colls)
+                    .map(|e| e.width())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let layout = LayoutBox {
+            width: 1 + column_widths.iter().sum::<usize>() + (num_colls - 1) + 1,
+            height: row_heights.iter().sum::<usize>() + num_rows.saturating_sub(1),
+            level: 0,
+        };
         Matrix {
             left_bracket,
             exprs,
             right_bracket,
+            bracket_style: options.bracket_style,
             num_colls,
+            column_widths,
+            row_heights,
+            layout,
         }
     }
+}
 
-    ///Gather max width per column and max height per row
-    ///for example:
-    ///[a*2, c] gives [ (3,1), (1,1)]
-    ///[b/2, d]       [ (3,3), (1,3)]
-    pub fn max_sizes(&self) -> Vec<Vec<(usize, usize)>> {
-        let num_rows = self.exprs.len() / self.num_colls;
-        let mut data = vec![vec![(0, 0); self.num_colls]; num_rows];
+impl Drawable for Matrix {
+    fn layout(&self) -> LayoutBox {
+        self.layout
+    }
 
-        for row_idx in 0..num_rows {
-            let max_row_height = (0..self.num_colls)
-                .map(|coll_idx| self.exprs[row_idx * self.num_colls + coll_idx].height())
-                .max()
-                .unwrap();
-            for coll_idx in 0..self.num_colls {
-                data[row_idx][coll_idx].1 = max_row_height;
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let LayoutBox { width, height, .. } = self.layout;
+        draw_bracket(
+            text_canvas,
+            glyphs,
+            &self.left_bracket,
+            self.bracket_style,
+            height,
+            at_x,
+            at_y,
+        );
+        let mut y = at_y;
+        for (row, row_height) in self.exprs.chunks(self.num_colls).zip(&self.row_heights) {
+            let mut x = at_x + 1;
+            for (expr, column_width) in row.iter().zip(&self.column_widths) {
+                expr.draw(
+                    text_canvas,
+                    glyphs,
+                    x + (column_width - expr.width()) / 2,
+                    y,
+                );
+                x += column_width + 1;
             }
+            y += row_height + 1;
         }
-        for coll_idx in 0..self.num_colls {
-            let max_col_width = (0..num_rows)
-                .map(|row_idx| self.exprs[row_idx * self.num_colls + coll_idx].width())
-                .max()
-                .unwrap();
-            for row_idx in 0..num_rows {
-                data[row_idx][coll_idx].0 = max_col_width;
-            }
+        draw_bracket(
+            text_canvas,
+            glyphs,
+            &self.right_bracket,
+            self.bracket_style,
+            height,
+            at_x + width - 1,
+            at_y,
+        );
+    }
+}
+
+//accent -> render expression with a mark above or below it
+//for example   ─→          ╱‾╲
+//   vec ab:    ab   hat abc: abc   ul ab: ab
+//                                     ▁▁
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccentType {
+    Hat,       //"^"
+    Bar,       //"‾"
+    Vector,    //"→"
+    Tilde,     //"˜"
+    Dot,       //"˙"
+    DoubleDot, //"¨"
+    Underline, //"▁" (below)
+}
+
+impl AccentType {
+    //combining character that puts this accent on a single grapheme
+    pub fn combining_char(&self) -> char {
+        match self {
+            AccentType::Hat => '\u{0302}',
+            AccentType::Bar => '\u{0304}',
+            AccentType::Vector => '\u{20D7}',
+            AccentType::Tilde => '\u{0303}',
+            AccentType::Dot => '\u{0307}',
+            AccentType::DoubleDot => '\u{0308}',
+            AccentType::Underline => '\u{0332}',
+        }
+    }
+
+    //asciimath command for this accent
+    pub fn command(&self) -> &'static str {
+        match self {
+            AccentType::Hat => "hat",
+            AccentType::Bar => "bar",
+            AccentType::Vector => "vec",
+            AccentType::Tilde => "tilde",
+            AccentType::Dot => "dot",
+            AccentType::DoubleDot => "ddot",
+            AccentType::Underline => "ul",
         }
-        data
+    }
+
+    pub fn is_under(&self) -> bool {
+        *self == AccentType::Underline
     }
 }
 
-impl Drawable for Matrix {
-    fn width(&self) -> usize {
-        let _num_rows = self.exprs.len() / self.num_colls;
-        let max_sizes = self.max_sizes();
-        1 + (0..self.num_colls)
-            .map(|coll_idx| max_sizes[0][coll_idx].0)
-            .sum::<usize>()
-            + (self.num_colls - 1)
-            + 1
+#[derive(Debug)]
+pub struct Accent {
+    accent_type: AccentType,
+    expr: Box<dyn Drawable>,
+    layout: LayoutBox,
+}
+
+impl Accent {
+    pub fn new(accent_type: AccentType, expr: Box<dyn Drawable>) -> Self {
+        let layout = LayoutBox {
+            width: expr.width(),
+            height: expr.height() + 1,
+            level: if accent_type.is_under() {
+                expr.level()
+            } else {
+                expr.level() + 1
+            },
+        };
+        Accent {
+            accent_type,
+            expr,
+            layout,
+        }
     }
+}
 
-    fn height(&self) -> usize {
-        let num_rows = self.exprs.len() / self.num_colls;
-        let max_sizes = self.max_sizes();
-        (0..num_rows)
-            .map(|row_idx| max_sizes[row_idx][0].1)
-            .sum::<usize>()
-            + (num_rows - 1)
+impl Drawable for Accent {
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let num_rows = self.exprs.len() / self.num_colls;
-        let max_sizes = self.max_sizes();
-        println!("sizes: {:?}", max_sizes);
-        let mut result = TextCanvas::new(self.width(), self.height());
-        draw_bracket(&mut result, &self.left_bracket, self.height(), 0, 0);
-        let mut y = 0;
-        for row_idx in 0..num_rows {
-            let mut x = 1;
-            let mut max_row_height = 0;
-            for coll_idx in 0..self.num_colls {
-                let tc = self.exprs[row_idx * self.num_colls + coll_idx].to_canvas();
-                result.draw(&tc, x + (max_sizes[row_idx][coll_idx].0 - tc.width) / 2, y);
-                x += max_sizes[row_idx][coll_idx].0 + 1;
-                if tc.height > max_row_height {
-                    max_row_height = tc.height
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let (expr_y, accent_y) = if self.accent_type.is_under() {
+            (at_y, at_y + self.expr.height())
+        } else {
+            (at_y + 1, at_y)
+        };
+        self.expr.draw(text_canvas, glyphs, at_x, expr_y);
+
+        let width = self.layout.width;
+        match self.accent_type {
+            //stretched across the whole expression
+            AccentType::Bar => {
+                for x in 0..width {
+                    text_canvas.set(at_x + x, accent_y, glyphs.get("‾"));
+                }
+            }
+            AccentType::Underline => {
+                for x in 0..width {
+                    text_canvas.set(at_x + x, accent_y, glyphs.get("▁"));
+                }
+            }
+            AccentType::Tilde => {
+                for x in 0..width {
+                    text_canvas.set(at_x + x, accent_y, glyphs.get("˜"));
+                }
+            }
+            //single ^ over one column, roof ╱‾╲ over wider expression
+            AccentType::Hat => {
+                if width == 1 {
+                    text_canvas.set(at_x, accent_y, glyphs.get("^"));
+                } else if width > 1 {
+                    text_canvas.set(at_x, accent_y, glyphs.get("╱"));
+                    for x in 1..width - 1 {
+                        text_canvas.set(at_x + x, accent_y, glyphs.get("‾"));
+                    }
+                    text_canvas.set(at_x + width - 1, accent_y, glyphs.get("╲"));
+                }
+            }
+            AccentType::Vector => {
+                for x in 0..width.saturating_sub(1) {
+                    text_canvas.set(at_x + x, accent_y, glyphs.get("─"));
+                }
+                if width > 0 {
+                    text_canvas.set(at_x + width - 1, accent_y, glyphs.get("→"));
+                }
+            }
+            //centered over the expression, dots don't stretch
+            AccentType::Dot | AccentType::DoubleDot => {
+                if width > 0 {
+                    let mark = match self.accent_type {
+                        AccentType::Dot => "˙",
+                        _ => "¨",
+                    };
+                    text_canvas.set(at_x + (width - 1) / 2, accent_y, glyphs.get(mark));
                 }
             }
-            y += max_row_height + 1;
         }
-        draw_bracket(
-            &mut result,
-            &self.right_bracket,
-            self.height(),
-            self.width() - 1,
-            0,
-        );
-        result
-    }
-
-    fn level(&self) -> usize {
-        0
     }
 }
 
@@ -645,6 +883,7 @@ pub struct ScriptExpr {
     expr: Box<dyn Drawable>,
     sup_expr: Option<Box<dyn Drawable>>,
     sub_expr: Option<Box<dyn Drawable>>,
+    layout: LayoutBox,
 }
 
 impl ScriptExpr {
@@ -653,74 +892,218 @@ impl ScriptExpr {
         sub_expr: Option<Box<dyn Drawable>>,
         sup_expr: Option<Box<dyn Drawable>>,
     ) -> Self {
+        let sup_layout = sup_expr.as_ref().map(|e| e.layout()).unwrap_or_default();
+        let sub_layout = sub_expr.as_ref().map(|e| e.layout()).unwrap_or_default();
+        let layout = LayoutBox {
+            width: expr.width() + std::cmp::max(sup_layout.width, sub_layout.width),
+            height: expr.height() + sup_layout.height + sub_layout.height,
+            level: expr.level() + sup_layout.height,
+        };
         ScriptExpr {
             expr,
             sup_expr,
             sub_expr,
+            layout,
         }
     }
 }
 
 impl Drawable for ScriptExpr {
-    fn width(&self) -> usize {
-        self.expr.width()
-            + match (&self.sup_expr, &self.sub_expr) {
-                (Some(e), None) => e.width(),
-                (None, Some(e)) => e.width(),
-                (Some(e1), Some(e2)) => std::cmp::max(e1.width(), e2.width()),
-                (None, None) => 0,
-            }
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn height(&self) -> usize {
-        self.expr.height()
-            + match (&self.sup_expr, &self.sub_expr) {
-                (Some(e), None) => e.height(),
-                (None, Some(e)) => e.height(),
-                (Some(e1), Some(e2)) => e1.height() + e2.height(),
-                (None, None) => 0,
-            }
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let sup_height = if let Some(e) = &self.sup_expr {
+            e.draw(text_canvas, glyphs, at_x + self.expr.width(), at_y);
+            e.height()
+        } else {
+            0
+        };
+        self.expr.draw(text_canvas, glyphs, at_x, at_y + sup_height);
+        if let Some(e) = &self.sub_expr {
+            e.draw(
+                text_canvas,
+                glyphs,
+                at_x + self.expr.width(),
+                at_y + sup_height + self.expr.height(),
+            );
+        }
     }
+}
 
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-        let expr_tc = self.expr.to_canvas();
-        match (&self.sup_expr, &self.sub_expr) {
-            (Some(e), None) => {
-                let tc = e.to_canvas();
-                result.draw(&tc, self.expr.width(), 0);
-                result.draw(&expr_tc, 0, e.height());
-                result
+//big operator -> sum, product or integral glyph as tall as its operand
+//for example   ╲▔▔   ┬─┬   ⌠
+//              ⟩     │ │   ⎮
+//              ╱▁▁   │ │   ⌡
+#[derive(Clone, Debug, PartialEq)]
+pub enum BigOperatorType {
+    Sum,      //"∑"
+    Product,  //"∏"
+    Integral, //"∫"
+}
+
+//operands lower than that (ie. x_n) don't make operator grow
+const MIN_STRETCH_HEIGHT: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct BigOperator {
+    operator_type: BigOperatorType,
+    layout: LayoutBox,
+}
+
+impl BigOperator {
+    //operand_height and operand_level are these of the expression operator applies to
+    pub fn new(
+        operator_type: BigOperatorType,
+        operand_height: usize,
+        operand_level: usize,
+    ) -> Self {
+        let (height, level) = if operand_height >= MIN_STRETCH_HEIGHT {
+            (operand_height, operand_level)
+        } else {
+            (1, 0)
+        };
+        let width = if height == 1 {
+            1
+        } else {
+            match operator_type {
+                BigOperatorType::Sum => (height - 1) / 2 + 2,
+                BigOperatorType::Product => std::cmp::max(height.div_ceil(2) + 1, 3),
+                BigOperatorType::Integral => 1,
             }
-            (None, Some(e)) => {
-                let tc = e.to_canvas();
-                result.draw(&expr_tc, 0, 0);
-                result.draw(&tc, self.expr.width(), self.expr.height());
-                result
+        };
+        BigOperator {
+            operator_type,
+            layout: LayoutBox {
+                width,
+                height,
+                level,
+            },
+        }
+    }
+}
+
+impl Drawable for BigOperator {
+    fn layout(&self) -> LayoutBox {
+        self.layout
+    }
+
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let LayoutBox { width, height, .. } = self.layout;
+        if height == 1 {
+            let glyph = match self.operator_type {
+                BigOperatorType::Sum => "∑",
+                BigOperatorType::Product => "∏",
+                BigOperatorType::Integral => "∫",
+            };
+            text_canvas.set(at_x, at_y, glyphs.get(glyph));
+            return;
+        }
+        match self.operator_type {
+            BigOperatorType::Sum => {
+                for y in 0..height / 2 {
+                    text_canvas.set(at_x + y, at_y + y, glyphs.get("╲"));
+                    text_canvas.set(at_x + y, at_y + height - y - 1, glyphs.get("╱"));
+                }
+                if !height.is_multiple_of(2) {
+                    text_canvas.set(at_x + height / 2, at_y + height / 2, glyphs.get("⟩"));
+                }
+                for x in 1..width {
+                    text_canvas.set(at_x + x, at_y, glyphs.get("▔"));
+                    text_canvas.set(at_x + x, at_y + height - 1, glyphs.get("▁"));
+                }
             }
-            (Some(e1), Some(e2)) => {
-                let tc1 = e1.to_canvas();
-                let tc2 = e2.to_canvas();
-                result.draw(&tc1, self.expr.width(), 0);
-                result.draw(&expr_tc, 0, e1.height());
-                result.draw(&tc2, self.expr.width(), e1.height() + self.expr.height());
-                result
+            BigOperatorType::Product => {
+                text_canvas.set(at_x, at_y, glyphs.get("┬"));
+                for x in 1..(width - 1) {
+                    text_canvas.set(at_x + x, at_y, glyphs.get("─"));
+                }
+                text_canvas.set(at_x + width - 1, at_y, glyphs.get("┬"));
+                for y in 1..height {
+                    text_canvas.set(at_x, at_y + y, glyphs.get("│"));
+                    text_canvas.set(at_x + width - 1, at_y + y, glyphs.get("│"));
+                }
             }
-            (None, None) => {
-                result.draw(&expr_tc, 0, 0);
-                result
+            BigOperatorType::Integral => {
+                text_canvas.set(at_x, at_y, glyphs.get("⌠"));
+                for y in 1..(height - 1) {
+                    text_canvas.set(at_x, at_y + y, glyphs.get("⎮"));
+                }
+                text_canvas.set(at_x, at_y + height - 1, glyphs.get("⌡"));
             }
         }
     }
+}
 
-    fn level(&self) -> usize {
-        match (&self.sup_expr, &self.sub_expr) {
-            (Some(e), Some(_)) | (Some(e), None) => self.expr.level() + e.height(),
-            (None, Some(_)) | (None, None) => self.expr.level(),
+//under/over -> render expression with limits centered below and above it
+//for example          N
+//   sum_(n=0)^N:      ∑
+//                    n=0
+#[derive(Debug)]
+pub struct UnderOver {
+    expr: Box<dyn Drawable>,
+    over_expr: Option<Box<dyn Drawable>>,
+    under_expr: Option<Box<dyn Drawable>>,
+    layout: LayoutBox,
+}
+
+impl UnderOver {
+    pub fn new(
+        expr: Box<dyn Drawable>,
+        under_expr: Option<Box<dyn Drawable>>,
+        over_expr: Option<Box<dyn Drawable>>,
+    ) -> Self {
+        let over_layout = over_expr.as_ref().map(|e| e.layout()).unwrap_or_default();
+        let under_layout = under_expr.as_ref().map(|e| e.layout()).unwrap_or_default();
+        let layout = LayoutBox {
+            width: *[expr.width(), over_layout.width, under_layout.width]
+                .iter()
+                .max()
+                .unwrap(),
+            height: over_layout.height + expr.height() + under_layout.height,
+            level: over_layout.height + expr.level(),
+        };
+        UnderOver {
+            expr,
+            over_expr,
+            under_expr,
+            layout,
+        }
+    }
+}
+
+impl Drawable for UnderOver {
+    fn layout(&self) -> LayoutBox {
+        self.layout
+    }
+
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let width = self.layout.width;
+        let over_height = if let Some(e) = &self.over_expr {
+            e.draw(
+                text_canvas,
+                glyphs,
+                at_x + (width - e.width()).div_ceil(2),
+                at_y,
+            );
+            e.height()
+        } else {
+            0
+        };
+        self.expr.draw(
+            text_canvas,
+            glyphs,
+            at_x + (width - self.expr.width()).div_ceil(2),
+            at_y + over_height,
+        );
+        if let Some(e) = &self.under_expr {
+            e.draw(
+                text_canvas,
+                glyphs,
+                at_x + (width - e.width()).div_ceil(2),
+                at_y + over_height + self.expr.height(),
+            );
         }
     }
 }
@@ -729,51 +1112,47 @@ impl Drawable for ScriptExpr {
 #[derive(Debug)]
 pub struct Sqrt {
     expr: Box<dyn Drawable>,
+    layout: LayoutBox,
 }
 
 impl Sqrt {
     pub fn new(expr: Box<dyn Drawable>) -> Self {
-        Sqrt { expr }
+        let layout = LayoutBox {
+            width: expr.width() + expr.height() + ((expr.height() as f64 * 0.5 + 0.5) as usize),
+            height: expr.height() + 1,
+            level: expr.height().div_ceil(2),
+        };
+        Sqrt { expr, layout }
     }
 }
 
 impl Drawable for Sqrt {
-    fn width(&self) -> usize {
-        self.expr.width() + self.expr.height() + ((self.expr.height() as f64 * 0.5 + 0.5) as usize)
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn height(&self) -> usize {
-        self.expr.height() + 1
-    }
-
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-        let expr_tc = self.expr.to_canvas();
-        result.draw(&expr_tc, self.width() - self.expr.width(), 1);
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let LayoutBox { width, height, .. } = self.layout;
+        self.expr.draw(
+            text_canvas,
+            glyphs,
+            at_x + width - self.expr.width(),
+            at_y + 1,
+        );
 
         let mut idx = 0;
         let m = (self.expr.height() as f64 * 0.5 + 0.5) as usize;
         for (pos, i) in (0..m).rev().enumerate() {
-            result.set(pos, self.height() - i - 1, "╲");
+            text_canvas.set(at_x + pos, at_y + height - i - 1, glyphs.get("╲"));
             idx += 1;
         }
         for (pos, i) in (idx..(idx + self.expr.height())).enumerate() {
-            result.set(i, self.height() - pos - 1, "╱");
+            text_canvas.set(at_x + i, at_y + height - pos - 1, glyphs.get("╱"));
             idx += 1
         }
         for i in idx..(idx + self.expr.width()) {
-            result.set(i, 0, "▁")
+            text_canvas.set(at_x + i, at_y, glyphs.get("▁"))
         }
-
-        result
-    }
-
-    fn level(&self) -> usize {
-        (self.expr.height() + 1) / 2
     }
 }
 
@@ -790,133 +1169,202 @@ impl Drawable for Sqrt {
 pub struct Root {
     index: Box<dyn Drawable>,
     radicand: Box<dyn Drawable>,
+    layout: LayoutBox,
 }
 
 impl Root {
     pub fn new(index: Box<dyn Drawable>, radicand: Box<dyn Drawable>) -> Self {
-        Root { index, radicand }
+        let radical_symbol_height = index.width().div_ceil(2);
+        let height = std::cmp::max(
+            index.height() + radical_symbol_height,
+            radicand.height() + 1,
+        );
+        let layout = LayoutBox {
+            width: radical_symbol_height * 2 +    //((index width + 1)/2)*2
+                radicand.height().saturating_sub(radical_symbol_height)
+                + radicand.width(),
+            height,
+            level: height - index.height().div_ceil(2),
+        };
+        Root {
+            index,
+            radicand,
+            layout,
+        }
     }
 }
 
 impl Drawable for Root {
-    fn width(&self) -> usize {
-        let radical_symbol_height = (self.index.width() + 1) / 2;
-        radical_symbol_height * 2 +    //((index width + 1)/2)*2
-        if self.radicand.height() > radical_symbol_height { self.radicand.height() - radical_symbol_height } else { 0 }
-        //self.index.height() + (self.index.height() + 1) / 2 - 1
-        + self.radicand.width()
-    }
-
-    fn height(&self) -> usize {
-        let radical_symbol_height = (self.index.width() + 1) / 2;
-        std::cmp::max(
-            self.index.height() + radical_symbol_height,
-            self.radicand.height() + 1,
-        )
-    }
-
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
-    }
-
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
-
-        let index_tc = self.index.to_canvas();
-        let radicand_tc = self.radicand.to_canvas();
-
-        let radical_symbol_height = (self.index.width() + 1) / 2;
-        result.draw(
-            &index_tc,
-            self.index.width() % 2,
-            self.height() - self.index.height() - radical_symbol_height,
+    fn layout(&self) -> LayoutBox {
+        self.layout
+    }
+
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        let LayoutBox { width, height, .. } = self.layout;
+        let radical_symbol_height = self.index.width().div_ceil(2);
+        self.index.draw(
+            text_canvas,
+            glyphs,
+            at_x + self.index.width() % 2,
+            at_y + height - self.index.height() - radical_symbol_height,
         );
 
         let mut x_idx = 0;
 
         for i in 0..radical_symbol_height {
-            result.set(i, self.height() - radical_symbol_height + i, "╲");
+            text_canvas.set(
+                at_x + i,
+                at_y + height - radical_symbol_height + i,
+                glyphs.get("╲"),
+            );
             x_idx += 1;
         }
         for i in 0..radical_symbol_height {
-            result.set(x_idx, self.height() - i - 1, "╱");
+            text_canvas.set(at_x + x_idx, at_y + height - i - 1, glyphs.get("╱"));
             x_idx += 1;
         }
-        let mut top_line_level = self.height() - radical_symbol_height;
+        let mut top_line_level = height - radical_symbol_height;
         if self.radicand.height() > radical_symbol_height {
             for i in 0..(self.radicand.height() - radical_symbol_height) {
-                result.set(x_idx, self.height() - radical_symbol_height - i - 1, "╱");
-                top_line_level = self.height() - radical_symbol_height - i - 1;
+                text_canvas.set(
+                    at_x + x_idx,
+                    at_y + height - radical_symbol_height - i - 1,
+                    glyphs.get("╱"),
+                );
+                top_line_level = height - radical_symbol_height - i - 1;
                 x_idx += 1;
             }
         }
-        for _ in 0..radicand_tc.width {
-            result.set(x_idx, top_line_level - 1, "▁");
+        for _ in 0..self.radicand.width() {
+            text_canvas.set(at_x + x_idx, at_y + top_line_level - 1, glyphs.get("▁"));
             x_idx += 1;
         }
 
-        result.draw(
-            &radicand_tc,
-            self.width() - self.radicand.width(),
-            (top_line_level) + (self.height() - top_line_level - self.radicand.height() + 1) / 2,
+        self.radicand.draw(
+            text_canvas,
+            glyphs,
+            at_x + width - self.radicand.width(),
+            at_y + top_line_level + (height - top_line_level - self.radicand.height()).div_ceil(2),
         );
-        result
     }
+}
 
-    fn level(&self) -> usize {
-        self.height() - (self.index.height() + 1) / 2
+//colored -> expression drawn in given colour
+//colour of nested expressions takes precedence
+#[derive(Debug)]
+pub struct Colored {
+    color: Color,
+    expr: Box<dyn Drawable>,
+}
+
+impl Colored {
+    pub fn new(color: Color, expr: Box<dyn Drawable>) -> Self {
+        Colored { color, expr }
     }
 }
 
-//Expression holding a row of items
+impl Drawable for Colored {
+    fn layout(&self) -> LayoutBox {
+        self.expr.layout()
+    }
+
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        self.expr.draw(text_canvas, glyphs, at_x, at_y);
+        for y in at_y..(at_y + self.expr.height()) {
+            for x in at_x..(at_x + self.expr.width()) {
+                if text_canvas.get_style(x, y).color.is_none() {
+                    text_canvas.set_style(
+                        x,
+                        y,
+                        CellStyle {
+                            color: Some(self.color),
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+//spanned -> expression that came from given part of input.
+//It's not visible in output, but records its box on canvas,
+//so that cells can be mapped back to the input
 #[derive(Debug)]
-pub struct Expr {
-    pub exprs: Vec<Box<dyn Drawable>>,
+pub struct Spanned {
+    span: Range<usize>,
+    kind: &'static str,
+    expr: Box<dyn Drawable>,
 }
 
-impl Expr {
-    pub fn new(exprs: Vec<Box<dyn Drawable>>) -> Self {
-        Expr { exprs }
+impl Spanned {
+    pub fn new(span: Range<usize>, kind: &'static str, expr: Box<dyn Drawable>) -> Self {
+        Spanned { span, kind, expr }
     }
 }
 
-impl Drawable for Expr {
-    fn width(&self) -> usize {
-        self.exprs.iter().map(|e| e.width()).sum()
+impl Drawable for Spanned {
+    fn layout(&self) -> LayoutBox {
+        self.expr.layout()
     }
 
-    fn height(&self) -> usize {
-        if self.exprs.is_empty() {
+    fn as_text(&self) -> String {
+        self.expr.as_text()
+    }
+
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
+        text_canvas.add_node_box(NodeBox {
+            span: self.span.clone(),
+            kind: self.kind,
+            x: at_x,
+            y: at_y,
+            width: self.width(),
+            height: self.height(),
+        });
+        self.expr.draw(text_canvas, glyphs, at_x, at_y);
+    }
+}
+
+//Expression holding a row of items
+#[derive(Debug)]
+pub struct Expr {
+    exprs: Vec<Box<dyn Drawable>>,
+    layout: LayoutBox,
+}
+
+impl Expr {
+    pub fn new(exprs: Vec<Box<dyn Drawable>>) -> Self {
+        let level = exprs.iter().map(|e| e.level()).max().unwrap_or(0);
+        let height = if exprs.is_empty() {
             0
         } else {
-            let level = self.level();
-            self.exprs
+            exprs
                 .iter()
-                .map(|e| level + (e.height() - e.level() - 1))
+                .map(|e| (level + e.height()).saturating_sub(e.level() + 1))
                 .max()
                 .unwrap()
                 + 1
-        }
+        };
+        let layout = LayoutBox {
+            width: exprs.iter().map(|e| e.width()).sum(),
+            height,
+            level,
+        };
+        Expr { exprs, layout }
     }
+}
 
-    fn as_text(&self) -> String {
-        self.to_canvas().as_text()
+impl Drawable for Expr {
+    fn layout(&self) -> LayoutBox {
+        self.layout
     }
 
-    fn to_canvas(&self) -> TextCanvas {
-        let mut result = TextCanvas::new(self.width(), self.height());
+    fn draw(&self, text_canvas: &mut TextCanvas, glyphs: &GlyphSet, at_x: usize, at_y: usize) {
         let mut idx = 0;
-        let level = self.level();
+        let level = self.layout.level;
         for expr in &self.exprs {
-            result.draw(&expr.to_canvas(), idx, level - expr.level());
+            expr.draw(text_canvas, glyphs, at_x + idx, at_y + level - expr.level());
             idx += expr.width();
         }
-
-        result
-    }
-
-    fn level(&self) -> usize {
-        self.exprs.iter().map(|e| e.level()).max().unwrap_or(0)
     }
 }
 
@@ -939,7 +1387,7 @@ mod test {
     fn test_div() {
         let l1 = Literal::new("1");
         let l2 = Literal::new("2");
-        let div = Div::new(Box::new(l1), Box::new(l2));
+        let div = Div::new(Box::new(l1), Box::new(l2), &RenderOptions::default());
 
         assert_eq!(div.width(), 3);
         assert_eq!(div.height(), 3);
@@ -967,13 +1415,55 @@ mod test {
         assert_eq!(&sqrt.as_text(), "  ▁\n╲╱1");
 
         let l2 = Literal::new("2");
-        let div = Div::new(Box::new(l1.clone()), Box::new(l2.clone()));
+        let div = Div::new(
+            Box::new(l1.clone()),
+            Box::new(l2.clone()),
+            &RenderOptions::default(),
+        );
         let sqrt = Sqrt::new(Box::new(div));
         assert_eq!(sqrt.width(), 8);
         assert_eq!(sqrt.height(), 4);
         assert_eq!(&sqrt.as_text(), "     ▁▁▁\n    ╱ 1 \n╲  ╱ ───\n ╲╱   2 ");
     }
 
+    #[test]
+    fn test_accent() {
+        let l1 = Literal::new("ab");
+        let accent = Accent::new(AccentType::Vector, Box::new(l1.clone()));
+        assert_eq!(accent.width(), 2);
+        assert_eq!(accent.height(), 2);
+        assert_eq!(accent.level(), 1);
+        assert_eq!(&accent.as_text(), "─→\nab");
+
+        let accent = Accent::new(AccentType::Underline, Box::new(l1.clone()));
+        assert_eq!(accent.level(), 0);
+        assert_eq!(&accent.as_text(), "ab\n▁▁");
+        assert_eq!(&accent.to_canvas(&GlyphSet::ascii()).as_text(), "ab\n__");
+
+        let accent = Accent::new(AccentType::Hat, Box::new(Literal::new("abc")));
+        assert_eq!(&accent.as_text(), "╱‾╲\nabc");
+        assert_eq!(&accent.to_canvas(&GlyphSet::ascii()).as_text(), "/-\\\nabc");
+        let accent = Accent::new(AccentType::Hat, Box::new(Literal::new("a")));
+        assert_eq!(&accent.as_text(), "^\na");
+
+        let accent = Accent::new(AccentType::Tilde, Box::new(l1.clone()));
+        assert_eq!(&accent.as_text(), "˜˜\nab");
+    }
+
+    #[test]
+    fn test_colored() {
+        let l1 = Literal::new("a");
+        let l2 = Literal::new("b");
+        let inner = Colored::new(Color::Blue, Box::new(l2));
+        let expr = Expr::new(vec![Box::new(l1), Box::new(inner)]);
+        let colored = Colored::new(Color::Red, Box::new(expr));
+        assert_eq!(&colored.as_text(), "ab");
+        assert_eq!(
+            &colored.to_canvas(&GlyphSet::default()).as_ansi_text(),
+            "\x1b[31ma\x1b[0m\x1b[34mb\x1b[0m"
+        );
+    }
+
     #[test]
     fn test_expression() {
         let expr = Expr::new(vec![]);
@@ -1001,12 +1491,12 @@ mod test {
         let mut example_asciimath = "";
         let mut example: Vec<String> = vec![];
         for line in read_to_string("tests.txt").unwrap().lines() {
-            if line.starts_with("##") {
+            if let Some(name) = line.strip_prefix("##") {
                 if mode == "example" {
                     verify(example_name, example_asciimath, &example.join("\n"));
                     example.clear();
                 }
-                example_name = line[2..].trim();
+                example_name = name.trim();
                 mode = "example_asciimath";
             } else if line.starts_with('#') || line.is_empty() {
                 if mode == "example" {
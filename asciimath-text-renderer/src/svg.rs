@@ -0,0 +1,135 @@
+//SVG output, for previews where terminal is not available.
+//Every non-blank cell becomes <text> element placed in a grid of fixed size cells,
+//stretched to exactly fill its cell(s), so the formula looks the same
+//as in the terminal, whatever font is used:
+//  <text x="0" y="10" textLength="10">x</text>
+
+use crate::html::escape;
+use crate::text_canvas::{Color, TextCanvas};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgOptions {
+    pub font_family: String,
+    pub font_size: f32,
+    //size of a single cell, in pixels
+    pub cell_width: f32,
+    pub cell_height: f32,
+    //colour of text without colour of its own
+    pub foreground: String,
+    pub background: Option<String>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            font_family: "monospace".to_string(),
+            font_size: 16.0,
+            cell_width: 10.0,
+            cell_height: 20.0,
+            foreground: "black".to_string(),
+            background: None,
+        }
+    }
+}
+
+fn fill(color: Color) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        color => color.name().unwrap_or_default().to_string(),
+    }
+}
+
+pub fn canvas_to_svg(canvas: &TextCanvas, options: &SvgOptions) -> String {
+    let width = canvas.width as f32 * options.cell_width;
+    let height = canvas.height as f32 * options.cell_height;
+    let mut result = format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" ",
+            "viewBox=\"0 0 {} {}\" font-family=\"{}\" font-size=\"{}\" fill=\"{}\" ",
+            "dominant-baseline=\"central\">\n"
+        ),
+        width,
+        height,
+        width,
+        height,
+        escape(&options.font_family),
+        options.font_size,
+        escape(&options.foreground)
+    );
+    if let Some(background) = &options.background {
+        result.push_str(&format!(
+            "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            width,
+            height,
+            escape(background)
+        ));
+    }
+    for y in 0..canvas.height {
+        for x in 0..canvas.width {
+            let grapheme = canvas.get(x, y);
+            //blanks and second halves of wide graphemes
+            if grapheme.trim().is_empty() {
+                continue;
+            }
+            let cells = if x + 1 < canvas.width && canvas.get(x + 1, y).is_empty() {
+                2
+            } else {
+                1
+            };
+            let color = match canvas.get_style(x, y).color {
+                Some(color) => format!(" fill=\"{}\"", fill(color)),
+                None => String::new(),
+            };
+            result.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" textLength=\"{}\" lengthAdjust=\"spacingAndGlyphs\"{}>{}</text>\n",
+                x as f32 * options.cell_width,
+                (y as f32 + 0.5) * options.cell_height,
+                cells as f32 * options.cell_width,
+                color,
+                escape(grapheme)
+            ));
+        }
+    }
+    result.push_str("</svg>");
+    result
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::text_canvas::CellStyle;
+
+    #[test]
+    fn test_canvas_to_svg() {
+        let mut tc = TextCanvas::new(3, 1);
+        tc.set(0, 0, "<");
+        tc.set_wide(1, 0, "面");
+        tc.set_style(
+            0,
+            0,
+            CellStyle {
+                color: Some(Color::Red),
+            },
+        );
+        let options = SvgOptions {
+            font_family: "Fira Code".to_string(),
+            background: Some("#fff".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            canvas_to_svg(&tc, &options),
+            concat!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"30\" height=\"20\" ",
+                "viewBox=\"0 0 30 20\" font-family=\"Fira Code\" font-size=\"16\" fill=\"black\" ",
+                "dominant-baseline=\"central\">\n",
+                "<rect width=\"30\" height=\"20\" fill=\"#fff\"/>\n",
+                "<text x=\"0\" y=\"10\" textLength=\"10\" lengthAdjust=\"spacingAndGlyphs\" ",
+                "fill=\"red\">&lt;</text>\n",
+                "<text x=\"10\" y=\"10\" textLength=\"20\" lengthAdjust=\"spacingAndGlyphs\">",
+                "面</text>\n",
+                "</svg>"
+            )
+        );
+    }
+}
@@ -6,52 +6,302 @@
 //creating array 3x3. However as memory is linear, we will store it
 //as a vec: [1,' ', 3, -, *, -, 2, ' ', 4]
 //because we use unicode, every grapheme (displayed element)
-//will be stored as a String
+//can take several bytes. Formulas are made of few distinct graphemes,
+//so canvas keeps each of them once, in a table, and cells only hold
+//index into it: [1,' ', 3, -, *, -, 2, ' ', 4] becomes
+//cells: [1, 0, 2, 3, 4, 3, 5, 0, 6] table: [' ', 1, 3, -, *, 2, 4]
+//Empty canvas is just a vec of zeros, pointing to ' '.
+//
+//Cells are terminal columns. Some graphemes (CJK, most emoji) are
+//displayed two columns wide, so they take two cells: grapheme itself,
+//followed by continuation cell holding empty string, so that
+//as_text emits the grapheme only once.
+//Zero-width graphemes (standalone combining marks, joiners) have no column
+//of their own, Literal attaches them to preceding grapheme.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthChar;
+
+//index of continuation cell in grapheme table
+const CONTINUATION: u32 = 1;
+
+//East Asian Ambiguous characters (±, ×, arrows, box drawing)
+//are one column wide in most terminals, but two in
+//terminals configured for CJK locales.
+//Only Literal nodes (formula text, ie. "+-" or "->") are measured with it.
+//Glyphs drawn by renderer (─ │ ‾, bracket pieces, box drawing parts
+//of sum and integral) always take one cell, whatever the setting,
+//as layout of every node is built from one-cell pieces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+//number of terminal columns grapheme takes: 0, 1 or 2
+pub fn grapheme_width(grapheme: &str, ambiguous_width: AmbiguousWidth) -> usize {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return 0;
+    };
+    //emoji presentation selector makes text symbols (ie. ☺) wide
+    if grapheme.contains('\u{FE0F}') {
+        return 2;
+    }
+    let width = match ambiguous_width {
+        AmbiguousWidth::Narrow => first.width(),
+        AmbiguousWidth::Wide => first.width_cjk(),
+    };
+    //control characters have no width
+    std::cmp::min(width.unwrap_or(0), 2)
+}
+
+//besides grapheme, every cell has a style (currently just a colour),
+//which is kept in separate vec of the same layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    Gray,
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    //accepts basic colour names and hex notation (#rgb or #rrggbb)
+    pub fn from_name(name: &str) -> Option<Color> {
+        let name = name.trim();
+        if let Some(hex) = name.strip_prefix('#') {
+            let digits: Vec<u8> = hex
+                .chars()
+                .map(|c| c.to_digit(16).map(|d| d as u8))
+                .collect::<Option<Vec<u8>>>()?;
+            return match digits[..] {
+                [r, g, b] => Some(Color::Rgb(r * 17, g * 17, b * 17)),
+                [r1, r2, g1, g2, b1, b2] => {
+                    Some(Color::Rgb(r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2))
+                }
+                _ => None,
+            };
+        }
+        match name.to_lowercase().as_str() {
+            "black" => Some(Color::Black),
+            "red" => Some(Color::Red),
+            "green" => Some(Color::Green),
+            "yellow" => Some(Color::Yellow),
+            "blue" => Some(Color::Blue),
+            "magenta" | "fuchsia" => Some(Color::Magenta),
+            "cyan" | "aqua" => Some(Color::Cyan),
+            "white" => Some(Color::White),
+            "gray" | "grey" => Some(Color::Gray),
+            "orange" => Some(Color::Rgb(255, 165, 0)),
+            "purple" => Some(Color::Rgb(128, 0, 128)),
+            "brown" => Some(Color::Rgb(165, 42, 42)),
+            "pink" => Some(Color::Rgb(255, 192, 203)),
+            _ => None,
+        }
+    }
+
+    //basic colour name, None for Rgb
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Color::Black => Some("black"),
+            Color::Red => Some("red"),
+            Color::Green => Some("green"),
+            Color::Yellow => Some("yellow"),
+            Color::Blue => Some("blue"),
+            Color::Magenta => Some("magenta"),
+            Color::Cyan => Some("cyan"),
+            Color::White => Some("white"),
+            Color::Gray => Some("gray"),
+            Color::Rgb(..) => None,
+        }
+    }
+
+    //SGR parameters setting this colour as foreground
+    pub fn sgr(&self) -> String {
+        match self {
+            Color::Black => "30".to_string(),
+            Color::Red => "31".to_string(),
+            Color::Green => "32".to_string(),
+            Color::Yellow => "33".to_string(),
+            Color::Blue => "34".to_string(),
+            Color::Magenta => "35".to_string(),
+            Color::Cyan => "36".to_string(),
+            Color::White => "37".to_string(),
+            Color::Gray => "90".to_string(),
+            Color::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CellStyle {
+    pub color: Option<Color>,
+}
+
+//box drawn by a node, and input bytes the node came from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeBox {
+    pub span: Range<usize>,
+    //what the node is: "number", "fraction", "script"...
+    pub kind: &'static str,
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
 
 //canvas we will be drawing on
 #[derive(Clone, Debug)]
 pub struct TextCanvas {
-    //each item in vec represents a cell, holding single grapheme
-    //(which is stored as a String to keep it simple)
-    //TODO: this could be replaced with reference to cached set of strings
-    data: Vec<String>,
+    //each item in vec represents a cell, holding index of its grapheme
+    cells: Vec<u32>,
+    styles: Vec<CellStyle>,
+    //every distinct grapheme used on canvas, in order of first use
+    graphemes: Vec<String>,
+    grapheme_indexes: HashMap<String, u32>,
+    //boxes of nodes, in drawing order (parents before children)
+    node_boxes: Vec<NodeBox>,
+    //for every cell, 1 + index of innermost node box covering it, 0 if none
+    cell_nodes: Vec<u32>,
     pub width: usize,
     pub height: usize,
 }
 
 impl TextCanvas {
     pub fn new(width: usize, height: usize) -> Self {
-        let mut data = Vec::with_capacity(width * height);
-        for _ in 0..(width * height) {
-            data.push(" ".to_string())
-        }
         TextCanvas {
-            data,
+            cells: vec![0; width * height],
+            styles: vec![CellStyle::default(); width * height],
+            graphemes: vec![" ".to_string(), "".to_string()],
+            grapheme_indexes: HashMap::from([(" ".to_string(), 0), ("".to_string(), CONTINUATION)]),
+            node_boxes: vec![],
+            cell_nodes: vec![0; width * height],
             width,
             height,
         }
     }
 
+    //index of grapheme in the table, adding it if it's not there yet
+    fn intern(&mut self, value: &str) -> u32 {
+        if let Some(idx) = self.grapheme_indexes.get(value) {
+            return *idx;
+        }
+        let idx = self.graphemes.len() as u32;
+        self.graphemes.push(value.to_string());
+        self.grapheme_indexes.insert(value.to_string(), idx);
+        idx
+    }
+
     pub fn get(&self, x: usize, y: usize) -> &str {
-        &self.data[y * self.width + x]
+        &self.graphemes[self.cells[y * self.width + x] as usize]
     }
 
     pub fn set(&mut self, x: usize, y: usize, value: &str) {
-        self.data[y * self.width + x] = value.to_string()
+        let idx = self.intern(value);
+        self.set_cell(x, y, idx)
+    }
+
+    //set grapheme taking two columns, x+1 becomes its continuation
+    pub fn set_wide(&mut self, x: usize, y: usize, value: &str) {
+        self.set(x, y, value);
+        self.set_cell(x + 1, y, CONTINUATION);
+    }
+
+    //overwriting half of wide grapheme would leave the other half
+    //dangling, so it's replaced with space
+    fn set_cell(&mut self, x: usize, y: usize, idx: u32) {
+        let pos = y * self.width + x;
+        if self.cells[pos] == CONTINUATION && idx != CONTINUATION && x > 0 {
+            self.cells[pos - 1] = 0;
+        }
+        if x + 1 < self.width && self.cells[pos + 1] == CONTINUATION {
+            self.cells[pos + 1] = 0;
+        }
+        self.cells[pos] = idx
+    }
+
+    //node is drawn in given box. Nodes drawn later (children) take
+    //precedence over earlier ones for cells they share
+    pub fn add_node_box(&mut self, node_box: NodeBox) {
+        self.node_boxes.push(node_box);
+        let node_box = self.node_boxes.last().unwrap();
+        for y in node_box.y..(node_box.y + node_box.height).min(self.height) {
+            for x in node_box.x..(node_box.x + node_box.width).min(self.width) {
+                self.cell_nodes[y * self.width + x] = self.node_boxes.len() as u32;
+            }
+        }
+    }
+
+    pub fn node_boxes(&self) -> &[NodeBox] {
+        &self.node_boxes
+    }
+
+    //innermost node drawn in cell
+    pub fn node_box_at(&self, x: usize, y: usize) -> Option<&NodeBox> {
+        match self.cell_nodes[y * self.width + x] {
+            0 => None,
+            idx => Some(&self.node_boxes[idx as usize - 1]),
+        }
     }
 
-    //draw another canvas content at given coordinates
+    pub fn get_style(&self, x: usize, y: usize) -> CellStyle {
+        self.styles[y * self.width + x]
+    }
+
+    pub fn set_style(&mut self, x: usize, y: usize, style: CellStyle) {
+        self.styles[y * self.width + x] = style
+    }
+
+    //draw another canvas content (with its styles) at given coordinates.
+    //Renderer draws nodes straight into one canvas, this is for composing
+    //already rendered canvases
     pub fn draw(&mut self, other_text_canvas: &TextCanvas, at_x: usize, at_y: usize) {
+        //translate other canvas table into ours once, not for every cell
+        let indexes: Vec<u32> = other_text_canvas
+            .graphemes
+            .iter()
+            .map(|g| self.intern(g))
+            .collect();
+        for x in 0..other_text_canvas.width {
+            for y in 0..other_text_canvas.height {
+                let other_idx = other_text_canvas.cells[y * other_text_canvas.width + x];
+                self.cells[(at_y + y) * self.width + at_x + x] = indexes[other_idx as usize];
+                self.set_style(at_x + x, at_y + y, other_text_canvas.get_style(x, y));
+            }
+        }
+        let offset = self.node_boxes.len() as u32;
+        self.node_boxes
+            .extend(other_text_canvas.node_boxes.iter().map(|b| NodeBox {
+                x: b.x + at_x,
+                y: b.y + at_y,
+                ..b.clone()
+            }));
         for x in 0..other_text_canvas.width {
             for y in 0..other_text_canvas.height {
-                self.set(at_x + x, at_y + y, other_text_canvas.get(x, y))
+                let idx = other_text_canvas.cell_nodes[y * other_text_canvas.width + x];
+                if idx > 0 {
+                    self.cell_nodes[(at_y + y) * self.width + at_x + x] = idx + offset;
+                }
             }
         }
     }
 
     pub fn as_text(&self) -> String {
         let mut result: String = String::with_capacity(
-            self.data.iter().map(|s| s.len()).sum::<usize>()
+            self.cells
+                .iter()
+                .map(|idx| self.graphemes[*idx as usize].len())
+                .sum::<usize>()
                 + (if self.height > 0 { self.height - 1 } else { 0 }),
         );
 
@@ -65,6 +315,53 @@ impl TextCanvas {
         }
         result
     }
+
+    //same as as_text, but with ANSI SGR escape sequences for styled cells.
+    //Neighbouring cells of the same style share one sequence,
+    //and style is reset at the end of every line.
+    pub fn as_ansi_text(&self) -> String {
+        self.ansi_text(false)
+    }
+
+    //as_ansi_text without trailing spaces, which are dropped before styling,
+    //so no escape sequences are left around them
+    pub fn as_trimmed_ansi_text(&self) -> String {
+        self.ansi_text(true)
+    }
+
+    fn ansi_text(&self, trim: bool) -> String {
+        let mut result = String::new();
+        for y in 0..self.height {
+            let mut current = CellStyle::default();
+            let width = if trim {
+                (0..self.width)
+                    .rposition(|x| !self.get(x, y).trim().is_empty())
+                    .map_or(0, |x| x + 1)
+            } else {
+                self.width
+            };
+            for x in 0..width {
+                let style = self.get_style(x, y);
+                if style != current {
+                    if current != CellStyle::default() {
+                        result.push_str("\x1b[0m");
+                    }
+                    if let Some(color) = style.color {
+                        result.push_str(&format!("\x1b[{}m", color.sgr()));
+                    }
+                    current = style;
+                }
+                result.push_str(self.get(x, y));
+            }
+            if current != CellStyle::default() {
+                result.push_str("\x1b[0m");
+            }
+            if y < self.height - 1 {
+                result.push('\n');
+            }
+        }
+        result
+    }
 }
 
 #[cfg(test)]
@@ -99,4 +396,106 @@ mod test {
         const EXPECTED: &str = concat!("ab  \n", "cd  \n", "    \n", "    ");
         assert_eq!(bigger_tc.as_text(), EXPECTED);
     }
+
+    #[test]
+    fn test_text_canvas_graphemes_stored_once() {
+        let mut tc = TextCanvas::new(3, 1);
+        tc.set(0, 0, "─");
+        tc.set(1, 0, "─");
+        tc.set(2, 0, "x̄");
+        assert_eq!(tc.as_text(), "──x̄");
+        assert_eq!(tc.graphemes, vec![" ", "", "─", "x̄"]);
+        let mut bigger_tc = TextCanvas::new(4, 1);
+        bigger_tc.set(0, 0, "x̄");
+        bigger_tc.draw(&tc, 1, 0);
+        assert_eq!(bigger_tc.as_text(), "x̄──x̄");
+        assert_eq!(bigger_tc.graphemes, vec![" ", "", "x̄", "─"]);
+    }
+
+    #[test]
+    fn test_text_canvas_node_boxes() {
+        let mut tc = TextCanvas::new(3, 2);
+        let outer = NodeBox {
+            span: 0..5,
+            kind: "group",
+            x: 0,
+            y: 0,
+            width: 3,
+            height: 2,
+        };
+        let inner = NodeBox {
+            span: 1..2,
+            kind: "identifier",
+            x: 1,
+            y: 1,
+            width: 1,
+            height: 1,
+        };
+        tc.add_node_box(outer.clone());
+        tc.add_node_box(inner.clone());
+        assert_eq!(tc.node_box_at(0, 0), Some(&outer));
+        assert_eq!(tc.node_box_at(1, 1), Some(&inner));
+        let mut bigger_tc = TextCanvas::new(4, 2);
+        bigger_tc.draw(&tc, 1, 0);
+        assert_eq!(bigger_tc.node_box_at(0, 0), None);
+        assert_eq!(
+            bigger_tc.node_box_at(2, 1).map(|b| (b.x, b.span.clone())),
+            Some((2, 1..2))
+        );
+    }
+
+    #[test]
+    fn test_text_canvas_wide_graphemes() {
+        let mut tc = TextCanvas::new(4, 2);
+        tc.set_wide(0, 0, "中");
+        tc.set(2, 0, "x");
+        tc.set_wide(1, 1, "🙂");
+        assert_eq!(tc.as_text(), concat!("中x \n", " 🙂 "));
+        assert_eq!(tc.get(1, 0), "");
+        //overwriting either half clears the other one
+        tc.set(0, 0, "a");
+        tc.set(2, 1, "b");
+        assert_eq!(tc.as_text(), concat!("a x \n", "  b "));
+    }
+
+    #[test]
+    fn test_grapheme_width() {
+        assert_eq!(grapheme_width("x", AmbiguousWidth::Narrow), 1);
+        assert_eq!(grapheme_width("x̄", AmbiguousWidth::Narrow), 1);
+        assert_eq!(grapheme_width("中", AmbiguousWidth::Narrow), 2);
+        assert_eq!(grapheme_width("☺\u{FE0F}", AmbiguousWidth::Narrow), 2);
+        assert_eq!(grapheme_width("\u{301}", AmbiguousWidth::Narrow), 0);
+        assert_eq!(grapheme_width("±", AmbiguousWidth::Narrow), 1);
+        assert_eq!(grapheme_width("±", AmbiguousWidth::Wide), 2);
+    }
+
+    #[test]
+    fn test_text_canvas_as_ansi_text() {
+        let red = CellStyle {
+            color: Some(Color::Red),
+        };
+        let mut tc = TextCanvas::new(3, 2);
+        tc.set(0, 0, "a");
+        tc.set(1, 0, "b");
+        tc.set(2, 0, "c");
+        tc.set_style(0, 0, red);
+        tc.set_style(1, 0, red);
+        tc.set_style(2, 1, red);
+        assert_eq!(tc.as_text(), "abc\n   ");
+        assert_eq!(tc.as_ansi_text(), "\x1b[31mab\x1b[0mc\n  \x1b[31m \x1b[0m");
+        assert_eq!(tc.as_trimmed_ansi_text(), "\x1b[31mab\x1b[0mc\n");
+        let mut bigger_tc = TextCanvas::new(4, 2);
+        bigger_tc.draw(&tc, 1, 0);
+        assert_eq!(bigger_tc.get_style(1, 0), red);
+        assert_eq!(bigger_tc.get_style(0, 0), CellStyle::default());
+    }
+
+    #[test]
+    fn test_color_from_name() {
+        assert_eq!(Color::from_name("red"), Some(Color::Red));
+        assert_eq!(Color::from_name("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(Color::from_name("#f80"), Some(Color::Rgb(255, 136, 0)));
+        assert_eq!(Color::from_name("nocolor"), None);
+        assert_eq!(Color::from_name("#ff"), None);
+    }
 }
@@ -0,0 +1,516 @@
+//Typst export, for reports written in Typst.
+//Walks the same asciimath_parser tree as visitors in asciimath module,
+//but writes Typst math (to be put between dollars) instead of building renderer nodes:
+//  sum_(n=1)^N 1/n^2  ->  sum_(n = 1)^N frac(1, n^2)
+//Symbols are written with their Typst names (alpha, arrow.r, integral),
+//symbols Typst has no name for as Unicode characters from SYMBOLS table,
+//which Typst accepts in math as they are.
+
+use phf::{phf_map, phf_set};
+
+use crate::asciimath::{
+    check_binary, check_unary, position, raw_argument, RenderError, BIG_OPERATORS, SYMBOLS,
+};
+use crate::font_style::FontStyle;
+use crate::text_canvas::Color;
+
+//Typst names of asciimath symbols
+static TYPST_SYMBOLS: phf::Map<&'static str, &'static str> = phf_map! {
+    "alpha" => "alpha",
+    "beta" => "beta",
+    "chi" => "chi",
+    "delta" => "delta",
+    "Delta" => "Delta",
+    "epsi" => "epsilon",
+    "eta" => "eta",
+    "gamma" => "gamma",
+    "Gamma" => "Gamma",
+    "iota" => "iota",
+    "kappa" => "kappa",
+    "lambda" => "lambda",
+    "Lambda" => "Lambda",
+    "lamda" => "lambda",
+    "Lamda" => "Lambda",
+    "mu" => "mu",
+    "nu" => "nu",
+    "omega" => "omega",
+    "Omega" => "Omega",
+    "phi" => "phi.alt",
+    "varphi" => "phi",
+    "Phi" => "Phi",
+    "pi" => "pi",
+    "Pi" => "Pi",
+    "psi" => "psi",
+    "Psi" => "Psi",
+    "rho" => "rho",
+    "sigma" => "sigma",
+    "Sigma" => "Sigma",
+    "tau" => "tau",
+    "theta" => "theta",
+    "vartheta" => "theta.alt",
+    "Theta" => "Theta",
+    "upsilon" => "upsilon",
+    "xi" => "xi",
+    "Xi" => "Xi",
+    "zeta" => "zeta",
+
+    "*" => "dot.op",
+    "**" => "ast.op",
+    "***" => "star.op",
+    "xx" => "times",
+    "|><" => "times.l",
+    "><|" => "times.r",
+    "|><|" => "join",
+    "-:" => "div",
+    "@" => "compose",
+    "o+" => "plus.circle",
+    "ox" => "times.circle",
+    "o." => "dot.circle",
+    "sum" => "sum",
+    "prod" => "product",
+    "^^" => "and",
+    "^^^" => "and.big",
+    "vv" => "or",
+    "vvv" => "or.big",
+    "nn" => "inter",
+    "nnn" => "inter.big",
+    "uu" => "union",
+    "uuu" => "union.big",
+
+    "!=" => "eq.not",
+    "<=" => "lt.eq",
+    "lt=" => "lt.eq",
+    ">=" => "gt.eq",
+    "gt=" => "gt.eq",
+    "mlt" => "lt.double",
+    "mgt" => "gt.double",
+    "-<" => "prec",
+    "-lt" => "prec",
+    ">-" => "succ",
+    "-<=" => "prec.eq",
+    ">-=" => "succ.eq",
+    "in" => "in",
+    "!in" => "in.not",
+    "sub" => "subset",
+    "sup" => "supset",
+    "sube" => "subset.eq",
+    "supe" => "supset.eq",
+    "-=" => "equiv",
+    "~=" => "tilde.equiv",
+    "~~" => "approx",
+    "~" => "tilde.op",
+    "prop" => "prop",
+
+    "not" => "not",
+    "=>" => "arrow.r.double",
+    "<=>" => "arrow.l.r.double",
+    "AA" => "forall",
+    "EE" => "exists",
+    "_|_" => "bot",
+    "TT" => "top",
+    "|--" => "tack.r",
+    "|==" => "models",
+
+    "uarr" => "arrow.t",
+    "darr" => "arrow.b",
+    "rarr" => "arrow.r",
+    "->" => "arrow.r",
+    ">->" => "arrow.r.tail",
+    "->>" => "arrow.r.twohead",
+    "|->" => "arrow.r.bar",
+    "larr" => "arrow.l",
+    "harr" => "arrow.l.r",
+    "rArr" => "arrow.r.double",
+    "lArr" => "arrow.l.double",
+    "hArr" => "arrow.l.r.double",
+
+    "int" => "integral",
+    "oint" => "integral.cont",
+    "del" => "partial",
+    "grad" => "nabla",
+    "+-" => "plus.minus",
+    "-+" => "minus.plus",
+    "O/" => "nothing",
+    "oo" => "infinity",
+    "aleph" => "aleph",
+    ":." => "therefore",
+    ":'" => "because",
+    "/_" => "angle",
+    "/_\\" => "triangle.stroked.t",
+    "frown" => "frown",
+    "cdots" => "dots.h.c",
+    "vdots" => "dots.v",
+    "ddots" => "dots.down",
+    "diamond" => "diamond.stroked.small",
+    "square" => "square.stroked",
+    "|__" => "floor.l",
+    "__|" => "floor.r",
+    "|~" => "ceil.l",
+    "~|" => "ceil.r",
+    "CC" => "CC",
+    "NN" => "NN",
+    "QQ" => "QQ",
+    "RR" => "RR",
+    "ZZ" => "ZZ",
+};
+
+//operators predefined in Typst math, others are written with op("...")
+static TYPST_OPERATORS: phf::Set<&'static str> = phf_set! {
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "csch", "deg",
+    "det", "dim", "exp", "gcd", "lcm", "lim", "ln", "log", "max", "min", "mod", "sec",
+    "sech", "sin", "sinh", "tan", "tanh",
+};
+
+//characters with special meaning in Typst math.
+//Commas and semicolons are escaped too, as they separate function arguments
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '/' | '^' | '_' | '&' | '$' | '#' | '"' | '@' | ',' | ';'
+        ) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn text(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+//multi-letter name of operator or function
+fn operator(name: &str, limits: bool) -> String {
+    //"f" and "g" are just letters
+    if TYPST_OPERATORS.contains(name) || name.chars().count() == 1 {
+        name.to_string()
+    } else if limits {
+        format!("op(\"{}\", limits: #true)", name)
+    } else {
+        format!("op(\"{}\")", name)
+    }
+}
+
+fn symbol(symbol: &str) -> String {
+    if let Some(name) = TYPST_SYMBOLS.get(symbol) {
+        return name.to_string();
+    }
+    let value = SYMBOLS.get(symbol).unwrap_or(&symbol);
+    if value.chars().count() > 1 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        if BIG_OPERATORS.contains(symbol) || TYPST_OPERATORS.contains(value) {
+            //"lim", "max"
+            operator(value, true)
+        } else {
+            //"and", "if"
+            text(value)
+        }
+    } else if value.trim().is_empty() {
+        match symbol {
+            "quad" => "quad".to_string(),
+            "qquad" => "wide".to_string(),
+            _ => "space".to_string(),
+        }
+    } else {
+        escape(value)
+    }
+}
+
+//Typst names of brackets, needed when they are not balanced
+fn bracket(bracket: &str) -> Option<(&'static str, &'static str)> {
+    match bracket {
+        "(" | "left(" => Some(("(", "paren.l")),
+        ")" | "right)" => Some((")", "paren.r")),
+        "[" | "left[" => Some(("[", "bracket.l")),
+        "]" | "right]" => Some(("]", "bracket.r")),
+        "{" => Some(("{", "brace.l")),
+        "}" => Some(("}", "brace.r")),
+        "|" | "|:" | ":|" => Some(("|", "bar.v")),
+        "(:" | "<<" | "langle" => Some(("angle.l", "angle.l")),
+        ":)" | ">>" | "rangle" => Some(("angle.r", "angle.r")),
+        _ => None,
+    }
+}
+
+fn is_balanced(left: &str, right: &str) -> bool {
+    matches!(
+        (left, right),
+        ("(", ")") | ("[", "]") | ("{", "}") | ("|", "|") | ("angle.l", "angle.r")
+    )
+}
+
+//content between brackets that grow with it
+fn lr(left: &str, content: &str, right: &str) -> String {
+    match (bracket(left), bracket(right)) {
+        (Some(left), Some(right)) if is_balanced(left.0, right.0) => {
+            format!("lr({} {} {})", left.0, content, right.0)
+        }
+        (None, None) => content.to_string(),
+        (left, right) => {
+            let items: Vec<&str> = [left.map(|b| b.1), Some(content), right.map(|b| b.1)]
+                .into_iter()
+                .flatten()
+                .collect();
+            format!("lr({})", items.join(" "))
+        }
+    }
+}
+
+fn font_function(font: FontStyle) -> &'static str {
+    match font {
+        FontStyle::Bold => "bold",
+        FontStyle::DoubleStruck => "bb",
+        FontStyle::Script => "cal",
+        FontStyle::Monospace => "mono",
+        FontStyle::Fraktur => "frak",
+        FontStyle::SansSerif => "sans",
+    }
+}
+
+fn fill(color: Color) -> String {
+    match color {
+        Color::Rgb(r, g, b) => format!("rgb(\"#{:02x}{:02x}{:02x}\")", r, g, b),
+        Color::Magenta => "fuchsia".to_string(),
+        Color::Cyan => "aqua".to_string(),
+        color => color.name().unwrap_or_default().to_string(),
+    }
+}
+
+//source is the converted string, used to report error positions
+pub fn typst_simple(
+    simple: &asciimath_parser::tree::Simple,
+    omit_braces: bool,
+    source: &str,
+) -> Result<String, RenderError> {
+    let arg = |simple| typst_simple(simple, true, source);
+    match simple {
+        asciimath_parser::tree::Simple::Missing => Ok(String::new()),
+        asciimath_parser::tree::Simple::Number(number) => Ok(number.to_string()),
+        asciimath_parser::tree::Simple::Text(t) => Ok(text(t)),
+        //multi-letter ident (ie. "dx") would be read as one variable
+        asciimath_parser::tree::Simple::Ident(ident)
+            if ident.chars().count() > 1 && ident.chars().all(char::is_alphabetic) =>
+        {
+            Ok(ident
+                .chars()
+                .map(String::from)
+                .collect::<Vec<_>>()
+                .join(" "))
+        }
+        asciimath_parser::tree::Simple::Ident(ident) => Ok(escape(ident)),
+        asciimath_parser::tree::Simple::Symbol(s) => Ok(symbol(s)),
+        asciimath_parser::tree::Simple::Unary(unary) => {
+            check_unary(unary.op, source)?;
+            let function = match unary.op {
+                "sqrt" => "sqrt",
+                "hat" => "hat",
+                "bar" | "overline" => "overline",
+                "vec" => "arrow",
+                "tilde" => "tilde",
+                "dot" => "dot",
+                "ddot" => "dot.double",
+                "ul" | "underline" => "underline",
+                op => match FontStyle::from_command(op) {
+                    Some(font) => font_function(font),
+                    None => {
+                        return Err(RenderError::UnsupportedUnary {
+                            op: op.to_string(),
+                            position: position(source, op),
+                        })
+                    }
+                },
+            };
+            Ok(format!("{}({})", function, arg(unary.arg())?))
+        }
+        asciimath_parser::tree::Simple::Func(func) => {
+            //function in simple context (ie. "sqrt sin x") can't have scripts
+            let name = operator(func.func, false);
+            match typst_simple(func.arg(), false, source)? {
+                arg if arg.is_empty() => Ok(name),
+                arg => Ok(format!("{} {}", name, arg)),
+            }
+        }
+        asciimath_parser::tree::Simple::Binary(binary) => {
+            check_binary(binary.op, source)?;
+            let first = arg(binary.first())?;
+            let second = arg(binary.second())?;
+            match binary.op {
+                "frac" => Ok(format!("frac({}, {})", first, second)),
+                "root" => Ok(format!("root({}, {})", first, second)),
+                "stackrel" => Ok(format!("limits({})^({})", second, first)),
+                "color" => {
+                    let color_name = raw_argument(binary.first(), source);
+                    let color =
+                        Color::from_name(color_name).ok_or_else(|| RenderError::UnknownColor {
+                            color: color_name.to_string(),
+                            position: position(source, color_name),
+                        })?;
+                    Ok(format!("#text(fill: {})[${}$]", fill(color), second))
+                }
+                op => Err(RenderError::UnsupportedBinary {
+                    op: op.to_string(),
+                    position: position(source, op),
+                }),
+            }
+        }
+        asciimath_parser::tree::Simple::Group(group) => {
+            let expr = typst_expr(&group.expr, source)?;
+            if omit_braces {
+                Ok(expr)
+            } else {
+                Ok(lr(group.left_bracket, &expr, group.right_bracket))
+            }
+        }
+        asciimath_parser::tree::Simple::Matrix(matrix) => {
+            let mut rows = vec![];
+            for row in matrix.rows() {
+                let cells = row
+                    .iter()
+                    .map(|e| typst_expr(e, source))
+                    .collect::<Result<Vec<String>, RenderError>>()?;
+                rows.push(cells.join(", "));
+            }
+            let rows = rows.join("; ");
+            let left = bracket(matrix.left_bracket).map(|b| b.0);
+            let right = bracket(matrix.right_bracket).map(|b| b.0);
+            match (left, right) {
+                (Some("("), Some(")")) => Ok(format!("mat({})", rows)),
+                (Some("angle.l"), Some("angle.r")) => Ok(format!("mat(delim: \"⟨\", {})", rows)),
+                (Some(left), Some(right)) if is_balanced(left, right) => {
+                    Ok(format!("mat(delim: \"{}\", {})", left, rows))
+                }
+                _ => Ok(lr(
+                    matrix.left_bracket,
+                    &format!("mat(delim: #none, {})", rows),
+                    matrix.right_bracket,
+                )),
+            }
+        }
+    }
+}
+
+//single numbers and letters are attached as they are, anything else in parentheses
+fn script_part(part: String) -> String {
+    if !part.is_empty() && part.chars().all(char::is_alphanumeric) {
+        part
+    } else {
+        format!("({})", part)
+    }
+}
+
+pub fn typst_script(
+    base: String,
+    script: &asciimath_parser::tree::Script,
+    source: &str,
+) -> Result<String, RenderError> {
+    let mut result = base;
+    if let Some(sub) = script.sub() {
+        result.push('_');
+        result.push_str(&script_part(typst_simple(sub, true, source)?));
+    }
+    if let Some(sup) = script.sup() {
+        result.push('^');
+        result.push_str(&script_part(typst_simple(sup, true, source)?));
+    }
+    Ok(result)
+}
+
+pub fn typst_script_func(
+    script_func: &asciimath_parser::tree::ScriptFunc,
+    omit_braces: bool,
+    source: &str,
+) -> Result<String, RenderError> {
+    match script_func {
+        asciimath_parser::tree::ScriptFunc::Simple(simple_script) => typst_script(
+            typst_simple(&simple_script.simple, omit_braces, source)?,
+            &simple_script.script,
+            source,
+        ),
+        asciimath_parser::tree::ScriptFunc::Func(func) => {
+            //scripts belong to function name, ie. "sin^2 x"
+            let name = typst_script(operator(func.func, false), &func.script, source)?;
+            match typst_script_func(func.arg(), false, source)? {
+                arg if arg.is_empty() => Ok(name),
+                arg => Ok(format!("{} {}", name, arg)),
+            }
+        }
+    }
+}
+
+pub fn typst_intermediate(
+    intermediate: &asciimath_parser::tree::Intermediate,
+    source: &str,
+) -> Result<String, RenderError> {
+    match intermediate {
+        asciimath_parser::tree::Intermediate::ScriptFunc(script_func) => {
+            typst_script_func(script_func, false, source)
+        }
+        asciimath_parser::tree::Intermediate::Frac(fraction) => Ok(format!(
+            "frac({}, {})",
+            typst_script_func(&fraction.numer, true, source)?,
+            typst_script_func(&fraction.denom, true, source)?
+        )),
+    }
+}
+
+//items are separated with spaces, as "a b" is a product, but "ab" a variable
+pub fn typst_expr(
+    expr: &asciimath_parser::tree::Expression,
+    source: &str,
+) -> Result<String, RenderError> {
+    let items = expr
+        .iter()
+        .map(|e| typst_intermediate(e, source))
+        .collect::<Result<Vec<String>, RenderError>>()?;
+    Ok(items
+        .into_iter()
+        .filter(|item| !item.is_empty())
+        .collect::<Vec<String>>()
+        .join(" "))
+}
+
+pub fn to_typst(expr: &str) -> Result<String, RenderError> {
+    typst_expr(&asciimath_parser::parse(expr), expr)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_to_typst() {
+        assert_eq!(to_typst("x^2+1").unwrap(), "x^2 + 1");
+        assert_eq!(to_typst("(a+b)/c").unwrap(), "frac(a + b, c)");
+        assert_eq!(to_typst("root 3 x").unwrap(), "root(3, x)");
+        assert_eq!(to_typst("sqrt(x+1)").unwrap(), "sqrt(x + 1)");
+        assert_eq!(to_typst("sum_(i=1)^n i^2").unwrap(), "sum_(i = 1)^n i^2");
+        assert_eq!(to_typst("lim_(x->0) x").unwrap(), "lim_(x arrow.r 0) x");
+        assert_eq!(to_typst("alpha <= oo").unwrap(), "alpha lt.eq infinity");
+        //no Typst name
+        assert_eq!(to_typst("a >->> b").unwrap(), "a ⤖ b");
+        assert_eq!(to_typst("(x)").unwrap(), "lr(( x ))");
+        assert_eq!(to_typst("(:x:)").unwrap(), "lr(angle.l x angle.r)");
+        assert_eq!(to_typst("[x)").unwrap(), "lr(bracket.l x paren.r)");
+        assert_eq!(to_typst("{: x :}").unwrap(), "x");
+        assert_eq!(
+            to_typst("[[a,b],[c,d]]").unwrap(),
+            "mat(delim: \"[\", a, b; c, d)"
+        );
+        assert_eq!(to_typst("((1),(2))").unwrap(), "mat(1; 2)");
+        assert_eq!(to_typst("f(a,b)").unwrap(), "f lr(( a \\, b ))");
+        assert_eq!(to_typst("sin^2 x").unwrap(), "sin^2 x");
+        assert_eq!(to_typst("int_0^1 x dx").unwrap(), "integral_0^1 x d x");
+        assert_eq!(to_typst("Sin x").unwrap(), "op(\"Sin\") x");
+        assert_eq!(to_typst("bbb R xx bb x").unwrap(), "bb(R) times bold(x)");
+        assert_eq!(to_typst("a//b").unwrap(), "a \\/ b");
+        assert_eq!(to_typst("a quad b").unwrap(), "a quad b");
+        assert_eq!(to_typst("x \"if\" y").unwrap(), "x \"if\" y");
+        assert_eq!(
+            to_typst("color(magenta)(x)").unwrap(),
+            "#text(fill: fuchsia)[$x$]"
+        );
+        assert!(to_typst("color(nope)(x)").is_err());
+    }
+}
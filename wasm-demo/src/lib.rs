@@ -6,4 +6,12 @@ pub fn render(s:&str) -> String {
     asciimath_text_renderer::render(s)
 }
 
+#[wasm_bindgen]
+pub fn render_html(s:&str) -> String {
+    asciimath_text_renderer::render_html(s)
+}
 
+#[wasm_bindgen]
+pub fn html_style() -> String {
+    asciimath_text_renderer::HTML_STYLE.to_string()
+}